@@ -1,22 +1,117 @@
 use bevy::prelude::*;
-#[cfg(debug)]
+#[cfg(feature = "inspector")]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 mod match3;
 use crate::match3::*;
 
+const USAGE: &str = "Usage: bevy-demo [options]
+  --seed <number>         Replay a game
+  --level <path>          Play a level from the assets folder, e.g. levels/hourglass.level.ron
+  --rows <number>         Size of the board, when there is no level
+  --columns <number>
+  --layout <layout>       rectangle, diamond or heart
+  --colors <number>       How many kinds of tile to play with, 3 to 7
+  --cell-size <pixels>
+  --hint-seconds <number> How long the player can be idle before a move is hinted
+  --no-drag               Leave out the mouse and touch input
+  --no-cursor             Leave out the keyboard and gamepad input
+  --no-hints
+  --no-effects
+  --no-ui                 Leave out the score, menu and messages";
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: String) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} must be a number, not {}", option, value))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Match3Plugin, String> {
+    let mut match3 = Match3Plugin::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--seed" => match3 = match3.seed(parse_number(&arg, value()?)?),
+            "--level" => match3 = match3.level(&value()?),
+            "--rows" | "--columns" => {
+                let size = parse_number(&arg, value()?)?;
+                // A board any smaller can't hold a match
+                if size < 3 {
+                    return Err(format!("{} must be at least 3", arg));
+                }
+                match3 = if arg == "--rows" { match3.rows(size) } else { match3.columns(size) };
+            }
+            "--layout" => {
+                match3 = match3.layout(match value()?.as_str() {
+                    "rectangle" => BoardLayout::Rectangle,
+                    "diamond" => BoardLayout::Diamond,
+                    "heart" => BoardLayout::Heart,
+                    layout => return Err(format!("Unknown layout {}", layout)),
+                });
+            }
+            "--colors" => {
+                let colors = parse_number(&arg, value()?)?;
+                if !(TileSet::MIN_COLORS..=TileSet::all().kinds.len()).contains(&colors) {
+                    return Err(format!("--colors must be between {} and {}", TileSet::MIN_COLORS, TileSet::all().kinds.len()));
+                }
+                match3 = match3.tile_set(TileSet::with_colors(colors));
+            }
+            "--cell-size" => match3 = match3.cell_size(parse_number(&arg, value()?)?),
+            "--hint-seconds" => match3 = match3.hint_seconds(parse_number(&arg, value()?)?),
+            "--no-drag" => match3 = match3.drag(false),
+            "--no-cursor" => match3 = match3.cursor(false),
+            "--no-hints" => match3 = match3.hints(false),
+            "--no-effects" => match3 = match3.effects(false),
+            "--no-ui" => match3 = match3.ui(false),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+    Ok(match3)
+}
+
 fn main(){
+    let match3 = match parse_args(std::env::args().skip(1)) {
+        Ok(match3) => match3,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
     let mut app = App::new();
     app.add_plugins(DefaultPlugins.set(WindowPlugin{
         primary_window: Some(Window {
             title: "Match3".to_string(),
-            resolution: (480., 480.).into(),
+            resolution: (560., 560.).into(),
             ..Default::default()
         }),
         ..Default::default()
     }));
-    #[cfg(debug)]
+    #[cfg(feature = "inspector")]
     app.add_plugin(WorldInspectorPlugin::new());
-    app.add_plugin(Match3Plugin)
+    app.add_plugin(match3)
         .run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn parse(args: &[&str]) -> Result<Match3Plugin, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+    #[test]
+    fn options_are_parsed() {
+        assert!(parse(&[]).is_ok());
+        assert!(parse(&["--seed", "42", "--level", "levels/hourglass.level.ron"]).is_ok());
+        assert!(parse(&["--rows", "8", "--columns", "7", "--layout", "heart", "--colors", "5", "--no-hints"]).is_ok());
+    }
+    #[test]
+    fn bad_options_are_rejected() {
+        assert!(parse(&["--seed", "abc"]).is_err());
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--level"]).is_err());
+        assert!(parse(&["--colour", "5"]).is_err());
+        assert!(parse(&["--rows", "2"]).is_err());
+        assert!(parse(&["--layout", "circle"]).is_err());
+        assert!(parse(&["--colors", "0"]).is_err());
+        assert!(parse(&["--colors", "1"]).is_err());
+        assert!(parse(&["--colors", "8"]).is_err());
+    }
+}
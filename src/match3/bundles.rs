@@ -1,6 +1,6 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, sprite::Anchor};
 
-use super::{components::*, resources::*};
+use super::{components::*, coords::cell_to_local, resources::*, grid::TileModifier};
 
 #[derive(Bundle)]
 pub struct GameBundle {
@@ -13,9 +13,7 @@ pub struct GameBundle {
 impl Default for GameBundle {
     fn default() -> Self {
         GameBundle {
-            game_data: GameData {
-                grid: vec![],
-            },
+            game_data: GameData::default(),
             name: Name::new("Match3 Game"),
             sprite: SpriteBundle {
                 ..Default::default()
@@ -31,3 +29,56 @@ pub struct TileBundle {
     pub name: Name,
     pub tile: Tile,
 }
+
+impl TileBundle {
+    pub fn new(tile: Tile, cell_size: f32, tile_textures: &TileTextures) -> Self {
+        TileBundle {
+            sprite: SpriteBundle {
+                transform: Transform::from_translation(cell_to_local((tile.column, tile.row), cell_size)),
+                texture: tile_textures.get(tile.tile_type),
+                sprite: Sprite {
+                    color: tile_textures.tint(tile.tile_type),
+                    custom_size: Some(Vec2::new(cell_size, cell_size)),
+                    anchor: Anchor::BottomLeft,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            name: Name::new(format!("Tile {} {}", tile.column, tile.row)),
+            tile,
+        }
+    }
+}
+
+// A plain sprite drawn over a tile to show which kind of special tile it is.
+#[derive(Bundle)]
+pub struct SpecialMarkerBundle {
+    #[bundle]
+    pub sprite: SpriteBundle,
+    pub name: Name,
+    pub marker: SpecialMarker,
+}
+
+impl SpecialMarkerBundle {
+    pub fn new(modifier: TileModifier, cell_size: f32) -> Self {
+        let (size, color) = match modifier {
+            TileModifier::StripedHorizontal => (Vec2::new(cell_size * 0.8, cell_size * 0.15), Color::rgba(1.0, 1.0, 1.0, 0.8)),
+            TileModifier::StripedVertical => (Vec2::new(cell_size * 0.15, cell_size * 0.8), Color::rgba(1.0, 1.0, 1.0, 0.8)),
+            TileModifier::Bomb => (Vec2::new(cell_size * 0.35, cell_size * 0.35), Color::rgba(0.1, 0.1, 0.1, 0.8)),
+            TileModifier::ColorBomb => (Vec2::new(cell_size * 0.45, cell_size * 0.45), Color::rgba(1.0, 0.2, 0.9, 0.8)),
+        };
+        SpecialMarkerBundle {
+            sprite: SpriteBundle {
+                transform: Transform::from_xyz(cell_size / 2., cell_size / 2., 0.5),
+                sprite: Sprite {
+                    custom_size: Some(size),
+                    color,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            name: Name::new(format!("{:?}", modifier)),
+            marker: SpecialMarker,
+        }
+    }
+}
@@ -1,10 +1,65 @@
 use bevy::prelude::*;
 
-use super::grid::TileType;
+use super::grid::{TileType, TileModifier};
 
 #[derive(Component, Debug, Reflect)]
 pub struct Tile {
     pub tile_type: TileType,
     pub column: usize,
     pub row: usize,
+    pub modifier: Option<TileModifier>,
 }
+
+#[derive(Component, Debug, Reflect)]
+pub struct SpecialMarker;
+
+// Added to the board once the grid has changed and needs resolving, and kept until a step finds nothing left to do.
+// swap holds the tiles the player swapped to get here, if any, until the first step has used it.
+// cascade counts the steps resolved so far.
+#[derive(Component, Debug, Default, Reflect)]
+pub struct ResolveBoard {
+    #[reflect(ignore)]
+    pub swap: Option<((usize, usize), (usize, usize))>,
+    pub cascade: u32,
+}
+
+// Added to the board when the player has no moves left and the tiles need shuffling.
+#[derive(Component, Debug, Reflect)]
+pub struct ShuffleBoard;
+
+// One of the two tiles of a suggested move, shown after the player has been idle for a while.
+#[derive(Component, Debug, Reflect)]
+pub struct HintTile;
+
+// A copy of a cleared tile that shrinks and fades away.
+#[derive(Component, Debug, Reflect)]
+pub struct ClearingTile {
+    pub timer: Timer,
+}
+
+#[derive(Component, Debug, Reflect)]
+pub struct Particle {
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+// Lets the board be played with the keyboard or a gamepad.
+// While selected, the next direction pressed swaps the tile under the cursor that way.
+#[derive(Component, Debug, Reflect)]
+pub struct SelectionCursor {
+    pub column: usize,
+    pub row: usize,
+    pub selected: bool,
+}
+
+// Covers the board, or shows a message, outside of play.
+#[derive(Component, Debug, Reflect)]
+pub struct Overlay;
+
+// The score in the corner of the screen while a game is on.
+#[derive(Component, Debug, Reflect)]
+pub struct ScoreText;
+
+// Draws the ice, chain or stone in one cell of the board.
+#[derive(Component, Debug, Reflect)]
+pub struct ObstacleSprite;
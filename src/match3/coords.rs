@@ -0,0 +1,90 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+
+use super::{grid::*, resources::*};
+
+// Where a cell's bottom left corner sits, relative to the bottom left corner of the board.
+pub fn cell_to_local((column, row): Coord, cell_size: f32) -> Vec3 {
+  Vec3::new(column as f32 * cell_size, row as f32 * cell_size, 0.0)
+}
+
+// The cell under a position relative to the board.
+// None if the position is off the board or over a hole.
+pub fn local_to_cell(grid: &Grid, position: Vec2, cell_size: f32) -> Option<Coord> {
+  if position.x < 0.0 || position.y < 0.0 {
+    return None;
+  }
+  let coord = ((position.x / cell_size) as usize, (position.y / cell_size) as usize);
+  if coord.0 >= grid.width() || coord.1 >= grid.height() || !grid.is_cell(coord) {
+    return None;
+  }
+  Some(coord)
+}
+
+pub fn cell_to_world(board: &GlobalTransform, coord: Coord, cell_size: f32) -> Vec3 {
+  board.transform_point(cell_to_local(coord, cell_size))
+}
+
+pub fn world_to_local(board: &GlobalTransform, position: Vec2) -> Vec2 {
+  board.affine().inverse().transform_point3(position.extend(0.0)).truncate()
+}
+
+pub fn world_to_cell(board: &GlobalTransform, grid: &Grid, position: Vec2, cell_size: f32) -> Option<Coord> {
+  local_to_cell(grid, world_to_local(board, position), cell_size)
+}
+
+// Maps positions on the screen onto the board, through the camera and the board's transform.
+// Screen positions are measured from the bottom left of the window, like the cursor position.
+#[derive(SystemParam)]
+pub struct BoardPointer<'w, 's> {
+  game_config: Res<'w, GameConfig>,
+  cameras: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+  boards: Query<'w, 's, (&'static GameData, &'static GlobalTransform)>,
+}
+
+impl<'w, 's> BoardPointer<'w, 's> {
+  fn screen_to_world(&self, position: Vec2) -> Option<Vec2> {
+    let (camera, camera_transform) = self.cameras.get_single().ok()?;
+    camera.viewport_to_world_2d(camera_transform, position)
+  }
+
+  // The position relative to the bottom left corner of the board, whether or not it is over a cell.
+  pub fn screen_to_local(&self, position: Vec2) -> Option<Vec2> {
+    let (_, board_transform) = self.boards.get_single().ok()?;
+    Some(world_to_local(board_transform, self.screen_to_world(position)?))
+  }
+
+  pub fn screen_to_cell(&self, position: Vec2) -> Option<Coord> {
+    let (game_data, board_transform) = self.boards.get_single().ok()?;
+    world_to_cell(board_transform, &game_data.grid, self.screen_to_world(position)?, self.game_config.cell_size)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn local_positions_map_to_cells() {
+    let grid = Grid::with_mask(BoardMask::from_rows(&[
+      "#.#",
+      "###",
+    ]));
+    assert_eq!(local_to_cell(&grid, Vec2::new(10.0, 10.0), 80.0), Some((0, 0)));
+    assert_eq!(local_to_cell(&grid, Vec2::new(239.0, 159.0), 80.0), Some((2, 1)));
+    assert_eq!(local_to_cell(&grid, Vec2::new(100.0, 100.0), 80.0), None);
+    assert_eq!(local_to_cell(&grid, Vec2::new(-1.0, 10.0), 80.0), None);
+    assert_eq!(local_to_cell(&grid, Vec2::new(241.0, 10.0), 80.0), None);
+    assert_eq!(local_to_cell(&grid, Vec2::new(10.0, 161.0), 80.0), None);
+  }
+  #[test]
+  fn world_positions_go_through_the_board_transform() {
+    let grid = Grid::with_mask(BoardMask::full(3, 3));
+    let board = GlobalTransform::from(Transform::from_xyz(-120.0, -120.0, 0.0).with_scale(Vec3::splat(0.5)));
+    assert_eq!(cell_to_world(&board, (2, 1), 80.0), Vec3::new(-40.0, -80.0, 0.0));
+    assert_eq!(world_to_cell(&board, &grid, Vec2::new(-39.0, -79.0), 80.0), Some((2, 1)));
+    assert_eq!(world_to_cell(&board, &grid, Vec2::new(-121.0, -100.0), 80.0), None);
+    for coord in grid.coords() {
+      let corner = cell_to_world(&board, coord, 80.0).truncate();
+      assert_eq!(world_to_cell(&board, &grid, corner + Vec2::splat(1.0), 80.0), Some(coord));
+    }
+  }
+}
@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+
+use super::{objectives::*, resolve::*, resources::*, shapes::*, state::*, system_sets::*};
+
+// Seconds added for each step of a cascade after the first
+pub const CASCADE_BONUS_SECONDS: f32 = 1.0;
+pub const LINE_4_BONUS_SECONDS: f32 = 1.0;
+// For lines of five and the L, T and plus shapes
+pub const BIG_SHAPE_BONUS_SECONDS: f32 = 2.0;
+
+pub fn time_bonus(shapes: &[Shape], cascade: u32) -> f32 {
+  let shape_bonus = shapes
+    .iter()
+    .map(|shape| match shape.kind {
+      ShapeKind::Line3 => 0.0,
+      ShapeKind::Line4 => LINE_4_BONUS_SECONDS,
+      ShapeKind::Line5 | ShapeKind::L | ShapeKind::T | ShapeKind::Plus => BIG_SHAPE_BONUS_SECONDS,
+    })
+    .sum::<f32>();
+  shape_bonus + cascade.saturating_sub(1) as f32 * CASCADE_BONUS_SECONDS
+}
+
+// The clock keeps running while the board resolves, but not while the game is paused.
+pub fn tick_countdown(
+  time: Res<Time>,
+  mut level_progress: ResMut<LevelProgress>,
+) {
+  let Some(time_left) = &mut level_progress.time_left else {
+    return;
+  };
+  if *time_left <= 0.0 {
+    return;
+  }
+  *time_left = (*time_left - time.delta_seconds()).max(0.0);
+}
+
+// Bonuses only count while there is time left.
+// A swap that is still resolving when the clock hits zero scores, but it can't bring the clock back.
+pub fn add_time_bonus(
+  mut tiles_cleared_evr: EventReader<TilesCleared>,
+  mut level_progress: ResMut<LevelProgress>,
+) {
+  for evt in tiles_cleared_evr.iter() {
+    let bonus = time_bonus(&evt.shapes, evt.cascade);
+    let time_limit = level_progress.time_limit;
+    if let (Some(time_left), Some(time_limit)) = (&mut level_progress.time_left, time_limit) {
+      if *time_left > 0.0 && bonus > 0.0 {
+        *time_left = (*time_left + bonus).min(time_limit);
+      }
+    }
+  }
+}
+
+pub fn out_of_time(level_progress: Res<LevelProgress>) -> bool {
+  level_progress.out_of_time()
+}
+
+pub fn add_countdown_to_app(app: &mut App) -> &mut App {
+  app
+    // Input stops as soon as the clock hits zero
+    .configure_set(MouseInput.run_if(not(out_of_time)))
+    .configure_set(GameEvents.run_if(not(out_of_time)))
+    .add_system(tick_countdown.run_if(in_state(GameState::Playing).or_else(in_state(GameState::Resolving))))
+    .add_system(add_time_bonus.after(Resolve))
+    // Resolving ends the level on its way back to Playing, otherwise it ends here
+    .add_system(check_level_end.after(tick_countdown).run_if(in_state(GameState::Playing).and_then(out_of_time)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn bigger_shapes_and_cascades_earn_more_time() {
+    let shape = |kind| Shape { kind, tiles: vec![], pivot: (0, 0), runs: vec![] };
+    assert_eq!(time_bonus(&[shape(ShapeKind::Line3)], 1), 0.0);
+    assert_eq!(time_bonus(&[shape(ShapeKind::Line4)], 1), LINE_4_BONUS_SECONDS);
+    assert_eq!(time_bonus(&[shape(ShapeKind::T), shape(ShapeKind::Line3)], 3), BIG_SHAPE_BONUS_SECONDS + 2.0 * CASCADE_BONUS_SECONDS);
+  }
+}
@@ -0,0 +1,221 @@
+use bevy::{prelude::*, ecs::system::SystemParam, sprite::Anchor};
+
+use super::{components::*, coords::*, drag::*, grid::*, resources::*, setup::*, state::*, system_sets::*};
+
+// How far the stick has to be pushed before it moves the cursor
+const STICK_THRESHOLD: f32 = 0.5;
+
+// The next cell from coord in the given direction, stepping over any holes.
+// None if there are no cells left that way.
+pub fn next_cell(grid: &Grid, coord: Coord, direction: SwapDirection) -> Option<Coord> {
+  let (mut column, mut row) = coord;
+  loop {
+    match direction {
+      SwapDirection::Left => column = column.checked_sub(1)?,
+      SwapDirection::Right => column += 1,
+      SwapDirection::Down => row = row.checked_sub(1)?,
+      SwapDirection::Up => row += 1,
+    }
+    if column >= grid.width() || row >= grid.height() {
+      return None;
+    }
+    if grid.is_cell((column, row)) {
+      return Some((column, row));
+    }
+  }
+}
+
+// The cell nearest the middle of the board, where the cursor starts.
+// None if the board has no cells at all.
+pub fn middle_cell(grid: &Grid) -> Option<Coord> {
+  let (middle_column, middle_row) = (grid.width() / 2, grid.height() / 2);
+  grid
+    .coords()
+    .min_by_key(|&(column, row)| column.abs_diff(middle_column).pow(2) + row.abs_diff(middle_row).pow(2))
+}
+
+fn key_direction(keys: &Input<KeyCode>) -> Option<SwapDirection> {
+  if keys.any_just_pressed([KeyCode::Left, KeyCode::A]) {
+    Some(SwapDirection::Left)
+  } else if keys.any_just_pressed([KeyCode::Right, KeyCode::D]) {
+    Some(SwapDirection::Right)
+  } else if keys.any_just_pressed([KeyCode::Up, KeyCode::W]) {
+    Some(SwapDirection::Up)
+  } else if keys.any_just_pressed([KeyCode::Down, KeyCode::S]) {
+    Some(SwapDirection::Down)
+  } else {
+    None
+  }
+}
+
+fn stick_direction(x: f32, y: f32) -> Option<SwapDirection> {
+  if x.abs().max(y.abs()) < STICK_THRESHOLD {
+    None
+  } else if x.abs() > y.abs() {
+    Some(if x > 0. { SwapDirection::Right } else { SwapDirection::Left })
+  } else {
+    Some(if y > 0. { SwapDirection::Up } else { SwapDirection::Down })
+  }
+}
+
+pub fn spawn_cursor(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+  game_data: Query<&GameData>,
+) {
+  let Some((column, row)) = game_data.get_single().ok().and_then(|game_data| middle_cell(&game_data.grid)) else {
+    return;
+  };
+  commands.spawn((
+    SpriteBundle {
+      visibility: Visibility::Hidden,
+      sprite: Sprite {
+        custom_size: Some(Vec2::new(game_config.cell_size, game_config.cell_size)),
+        anchor: Anchor::BottomLeft,
+        ..Default::default()
+      },
+      ..Default::default()
+    },
+    SelectionCursor {
+      column,
+      row,
+      selected: false,
+    },
+    Name::new("Cursor"),
+  ));
+}
+
+pub fn despawn_cursor(
+  mut commands: Commands,
+  cursors: Query<Entity, With<SelectionCursor>>,
+) {
+  for entity in cursors.iter() {
+    commands.entity(entity).despawn_recursive();
+  }
+}
+
+#[derive(SystemParam)]
+pub struct GamepadInput<'w> {
+  gamepads: Res<'w, Gamepads>,
+  buttons: Res<'w, Input<GamepadButton>>,
+  axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+// Arrow keys, WASD, the D-pad or the left stick move the cursor.
+// Enter, Space or the A button picks up the tile under it, and the next direction swaps it that way.
+pub fn handle_cursor_input(
+  keys: Res<Input<KeyCode>>,
+  gamepad_input: GamepadInput,
+  mut last_stick_direction: Local<Option<SwapDirection>>,
+  game_data: Query<&GameData>,
+  mut cursors: Query<(&mut SelectionCursor, &mut Visibility)>,
+  mut tile_swap_evw: EventWriter<TileSwap>,
+) {
+  let mut direction = key_direction(&keys);
+  let mut select = keys.any_just_pressed([KeyCode::Return, KeyCode::Space]);
+  let mut deselect = false;
+  let mut stick = None;
+  for gamepad in gamepad_input.gamepads.iter() {
+    let pressed = |button_type| gamepad_input.buttons.just_pressed(GamepadButton::new(gamepad, button_type));
+    direction = direction
+      .or(pressed(GamepadButtonType::DPadLeft).then_some(SwapDirection::Left))
+      .or(pressed(GamepadButtonType::DPadRight).then_some(SwapDirection::Right))
+      .or(pressed(GamepadButtonType::DPadUp).then_some(SwapDirection::Up))
+      .or(pressed(GamepadButtonType::DPadDown).then_some(SwapDirection::Down));
+    select |= pressed(GamepadButtonType::South);
+    deselect |= pressed(GamepadButtonType::East);
+    let axis = |axis_type| gamepad_input.axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+    stick = stick.or(stick_direction(axis(GamepadAxisType::LeftStickX), axis(GamepadAxisType::LeftStickY)));
+  }
+  // The stick only moves the cursor once each time it is pushed
+  if stick != *last_stick_direction {
+    *last_stick_direction = stick;
+    direction = direction.or(stick);
+  }
+  if direction.is_none() && !select && !deselect {
+    return;
+  }
+
+  let Ok(game_data) = game_data.get_single() else {
+    return;
+  };
+  for (mut cursor, mut visibility) in cursors.iter_mut() {
+    *visibility = Visibility::Visible;
+    let coord = (cursor.column, cursor.row);
+    if deselect {
+      cursor.selected = false;
+    } else if select {
+      cursor.selected = !cursor.selected && game_data.grid.get(coord).is_some();
+    } else if let Some(direction) = direction {
+      if cursor.selected {
+        cursor.selected = false;
+        tile_swap_evw.send(TileSwap { tile: coord, direction });
+      } else if let Some((column, row)) = next_cell(&game_data.grid, coord, direction) {
+        cursor.column = column;
+        cursor.row = row;
+      }
+    }
+  }
+}
+
+// The cursor isn't a child of the board, so it is placed through the board's transform.
+pub fn update_cursor(
+  game_config: Res<GameConfig>,
+  boards: Query<&GlobalTransform, With<GameData>>,
+  mut cursors: Query<(&SelectionCursor, &mut Transform, &mut Sprite), Changed<SelectionCursor>>,
+) {
+  let Ok(board) = boards.get_single() else {
+    return;
+  };
+  for (cursor, mut transform, mut sprite) in cursors.iter_mut() {
+    transform.translation = cell_to_world(board, (cursor.column, cursor.row), game_config.cell_size) + Vec3::Z * 5.0;
+    sprite.color = if cursor.selected {
+      Color::rgba(1.0, 0.85, 0.2, 0.5)
+    } else {
+      Color::rgba(1.0, 1.0, 1.0, 0.3)
+    };
+  }
+}
+
+pub fn add_cursor_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<SelectionCursor>()
+    // The board is spawned when leaving the menu too, and the cursor needs it to find its first cell
+    .add_systems((apply_system_buffers, spawn_cursor).chain().after(setup_grid).in_schedule(OnExit(GameState::MainMenu)))
+    .add_system(despawn_cursor.in_schedule(OnEnter(GameState::MainMenu)))
+    .add_system(handle_cursor_input.in_set(MouseInput))
+    .add_system(update_cursor.after(MouseInput))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn next_cell_steps_over_holes() {
+    let grid = Grid::with_mask(BoardMask::from_rows(&[
+      "#.#",
+      "###",
+    ]));
+    assert_eq!(next_cell(&grid, (0, 1), SwapDirection::Right), Some((2, 1)));
+    assert_eq!(next_cell(&grid, (1, 0), SwapDirection::Up), None);
+    assert_eq!(next_cell(&grid, (0, 0), SwapDirection::Left), None);
+    assert_eq!(next_cell(&grid, (2, 0), SwapDirection::Up), Some((2, 1)));
+  }
+  #[test]
+  fn the_cursor_starts_on_the_cell_nearest_the_middle() {
+    let grid = Grid::with_mask(BoardMask::from_rows(&[
+      "#.#",
+      "#.#",
+      "###",
+    ]));
+    assert_eq!(middle_cell(&Grid::with_mask(BoardMask::full(6, 6))), Some((3, 3)));
+    assert_eq!(middle_cell(&grid), Some((0, 1)));
+    assert_eq!(middle_cell(&Grid::with_mask(BoardMask::full(0, 0))), None);
+  }
+  #[test]
+  fn stick_direction_needs_a_firm_push() {
+    assert_eq!(stick_direction(0.2, 0.3), None);
+    assert_eq!(stick_direction(0.8, 0.3), Some(SwapDirection::Right));
+    assert_eq!(stick_direction(-0.1, -0.9), Some(SwapDirection::Down));
+  }
+}
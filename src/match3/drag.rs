@@ -1,6 +1,9 @@
 use bevy::{prelude::*, utils::{HashSet, HashMap}};
 
-use super::{components::*, resources::*, grid::*};
+use super::{components::*, coords::*, resources::*, grid::*, specials::*, state::*, system_sets::*, tween::*};
+
+// The tile being dragged and the one it would be swapped with
+type DragTiles = Or<(With<SwapTile>, With<UnderSwapTile>)>;
 
 #[derive(Debug, Clone, Copy)]
 pub struct TileDragStart{
@@ -14,10 +17,11 @@ pub struct TileDragMove {
     pub delta_coord: Vec2,
 }
 
+// Asks to swap a tile with its neighbour in the given direction.
 #[derive(Debug, Clone, Copy)]
 pub struct TileSwap {
-    pub from: Vec2,
-    pub to: Vec2,
+    pub tile: Coord,
+    pub direction: SwapDirection,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -26,15 +30,11 @@ pub struct TileDragCancel {}
 #[derive(Debug, Clone, Copy)]
 pub struct TileDragEnd {}
 
+// Sent when a swap is accepted, whether the tile was dragged or moved with the keyboard cursor.
 #[derive(Debug, Clone, Copy)]
-pub struct TileDragSuccess {}
-
-#[derive(Reflect, Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub enum SwapDirection {
-    Left,
-    Right,
-    Up,
-    Down,
+pub struct TileDragSuccess {
+    pub from: Coord,
+    pub to: Coord,
 }
 
 #[derive(Component, Debug, Reflect)]
@@ -62,6 +62,11 @@ pub struct DragNeighbours {
 pub struct ResetDrag;
 
 const MIN_RAD:f32 = 0.2;
+const SWAP_SECONDS: f32 = 0.15;
+const BOUNCE_SECONDS: f32 = 0.3;
+const RESET_SECONDS: f32 = 0.1;
+// How far towards its neighbour, in cells, a tile is nudged when a keyboard swap is refused
+const NUDGE: f32 = 0.25;
 
 impl TileIsDragging {
   pub fn live_delta(&self) -> Vec2 {
@@ -80,10 +85,10 @@ impl TileIsDragging {
     }
     match self.direction {
         Some(SwapDirection::Left)|Some(SwapDirection::Right) => {
-            constrained_delta.y = constrained_delta.y.max(-MIN_RAD).min(MIN_RAD);
+            constrained_delta.y = constrained_delta.y.clamp(-MIN_RAD, MIN_RAD);
         }
         Some(SwapDirection::Down)|Some(SwapDirection::Up) => {
-            constrained_delta.x = constrained_delta.x.max(-MIN_RAD).min(MIN_RAD);
+            constrained_delta.x = constrained_delta.x.clamp(-MIN_RAD, MIN_RAD);
         }
         None => {}
     }
@@ -126,52 +131,39 @@ pub struct ResetTile;
 pub fn handle_tile_drag_start(
     mut commands: Commands,
     mut tile_drag_start_evr: EventReader<TileDragStart>,
-    game_config: Res<GameConfig>,
     mut game_data: Query<(Entity, &GameData)>,
     mut tiles: Query<(Entity, &Tile)>
   ) {
     for evt in tile_drag_start_evr.iter() {
       println!("Tile drag start: {:?}", evt);
-      for (entity, _) in game_data.iter_mut() {
-        let mut banned_directions = HashSet::new();
-        if evt.column == 0 {
-          banned_directions.insert(SwapDirection::Left);
-        }
-        if evt.column == game_config.columns - 1 {
-          banned_directions.insert(SwapDirection::Right);
-        }
-        if evt.row == 0 {
-          banned_directions.insert(SwapDirection::Down);
-        }
-        if evt.row == game_config.rows - 1 {
-          banned_directions.insert(SwapDirection::Up);
-        }
+      for (entity, game_data) in game_data.iter_mut() {
+        let dragging_tile = (evt.column, evt.row);
+        // A locked tile can't be dragged at all, and nothing can be swapped into a locked neighbour
+        let locked = game_data.grid.is_locked(dragging_tile);
+        let banned_directions = SwapDirection::ALL
+          .into_iter()
+          .filter(|&direction| {
+            locked || game_data.grid.neighbour(dragging_tile, direction).is_none_or(|neighbour| game_data.grid.is_locked(neighbour))
+          })
+          .collect::<HashSet<_>>();
         commands.entity(entity).insert(TileIsDragging {
           start_coord: evt.start_coord,
           delta_coord: Vec2::new(0.0, 0.0),
-          dragging_tile: (evt.column, evt.row),
+          dragging_tile,
           direction: None,
           banned_directions,
         }).insert(DragDirection {
           direction: None,
         });
+        let neighbour_coords = game_data.grid.neighbours(dragging_tile).collect::<Vec<_>>();
         let mut neighbours = HashMap::new();
 
         for (entity, tile) in tiles.iter_mut() {
-          if tile.column == evt.column {
-            if tile.row == evt.row {
-              commands.entity(entity).insert(SwapTile);
-            } else if tile.row == evt.row - 1 {
-              neighbours.insert(SwapDirection::Down, entity);
-            } else if tile.row == evt.row + 1 {
-              neighbours.insert(SwapDirection::Up, entity);
-            }
-          } else if tile.row == evt.row {
-            if tile.column == evt.column - 1 {
-              neighbours.insert(SwapDirection::Left, entity);
-            } else if tile.column == evt.column + 1 {
-              neighbours.insert(SwapDirection::Right, entity);
-            }
+          let coord = (tile.column, tile.row);
+          if coord == dragging_tile {
+            commands.entity(entity).insert(SwapTile);
+          } else if let Some((direction, _)) = neighbour_coords.iter().find(|(_, neighbour)| *neighbour == coord) {
+            neighbours.insert(*direction, entity);
           }
         }
         commands.entity(entity).insert(DragNeighbours {
@@ -183,67 +175,106 @@ pub fn handle_tile_drag_start(
   
 pub fn handle_tile_drag_end(
   mut commands: Commands,
-  game_config: Res<GameConfig>,
   mut tile_drag_end_evr: EventReader<TileDragEnd>,
-  mut game_data: Query<(Entity, &mut GameData, &DragDirection, &TileIsDragging)>,
-  mut swap_tile: Query<(Entity, &mut Tile, Option<&SwapTile>, &mut Transform, &mut Name), Or<(With<SwapTile>, With<UnderSwapTile>)>>,
+  mut tile_swap_evw: EventWriter<TileSwap>,
+  game_data: Query<(Entity, &DragDirection, &TileIsDragging), Without<ResetDrag>>,
+  swap_tile: Query<Entity, DragTiles>,
 ) {
   if tile_drag_end_evr.iter().next().is_none() {
     return;
   }
   
-  for (entity, mut game_data, drag_direction, tile_is_dragging) in game_data.iter_mut() {
-    match drag_direction.direction {
-      None => {
-        commands.entity(entity).insert(ResetDrag);  
-      },
-      Some(direction) => {
-        let tile1 = tile_is_dragging.dragging_tile;
-        let tile2 = match direction {
-          SwapDirection::Left => (tile1.0 - 1, tile1.1),
-          SwapDirection::Right => (tile1.0 + 1, tile1.1),
-          SwapDirection::Down => (tile1.0, tile1.1 - 1),
-          SwapDirection::Up => (tile1.0, tile1.1 + 1),
-        };
-        if can_swap(&mut game_data.grid, tile1, tile2) {
-          for (_, mut tile, is_swap_tile, mut transform, mut name) in swap_tile.iter_mut() {
-            if is_swap_tile.is_some() {
-              tile.column = tile2.0;
-              tile.row = tile2.1;
-              transform.translation = Vec3::new(
-                game_config.cell_size * tile2.0 as f32,
-                game_config.cell_size * tile2.1 as f32,
-                0.0,
-              );
-              name.set(format!("Tile {} {}", tile2.0, tile2.1));
-            } else {
-              tile.column = tile1.0;
-              tile.row = tile1.1;
-              transform.translation = Vec3::new(
-                game_config.cell_size * tile1.0 as f32,
-                game_config.cell_size * tile1.1 as f32,
-                0.0,
-              );
-              name.set(format!("Tile {} {}", tile1.0, tile1.1));
-            }
-          }
-          swap_tiles(&mut game_data.grid, tile1, tile2);
-          
-        } 
-        commands.entity(entity).insert(ResetDrag);
-      }
+  for (entity, drag_direction, tile_is_dragging) in game_data.iter() {
+    if let Some(direction) = drag_direction.direction {
+      tile_swap_evw.send(TileSwap {
+        tile: tile_is_dragging.dragging_tile,
+        direction,
+      });
     }
-    for (tile_entity, _, _, _, _) in swap_tile.iter_mut() {
+    commands.entity(entity).insert(ResetDrag);
+    for tile_entity in swap_tile.iter() {
       commands.entity(tile_entity).insert(ResetTile);
     }
+  }
 }
+
+// Swap a tile with its neighbour if that makes a match or sets off a special tile, otherwise spring both back.
+// Dragging with the mouse or a finger and moving the keyboard cursor all end up here.
+pub fn handle_tile_swap(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+  mut tile_swap_evr: EventReader<TileSwap>,
+  mut next_state: ResMut<NextState<GameState>>,
+  mut tile_drag_success_evw: EventWriter<TileDragSuccess>,
+  mut game_data: Query<(Entity, &mut GameData), Without<ResolveBoard>>,
+  mut tiles: Query<(Entity, &mut Tile, &Transform, &mut Name)>,
+) {
+  // Only one swap can be resolved at a time
+  let Some(&evt) = tile_swap_evr.iter().next() else {
+    return;
+  };
+  let cell_position = |coord| cell_to_local(coord, game_config.cell_size);
+  for (board, mut game_data) in game_data.iter_mut() {
+    let tile1 = evt.tile;
+    let neighbour = game_data.grid.neighbour(tile1, evt.direction);
+    let tile2 = neighbour.filter(|&tile2| {
+      let occupied = game_data.grid.get(tile1).is_some() && game_data.grid.get(tile2).is_some();
+      let locked = game_data.grid.is_locked(tile1) || game_data.grid.is_locked(tile2);
+      occupied && !locked && (activates_on_swap(&game_data.specials, tile1, tile2) || can_swap(&mut game_data.grid, tile1, tile2))
+    });
+    if let Some(tile2) = tile2 {
+      for (tile_entity, mut tile, transform, mut name) in tiles.iter_mut() {
+        let coord = (tile.column, tile.row);
+        let (column, row) = if coord == tile1 {
+          tile2
+        } else if coord == tile2 {
+          tile1
+        } else {
+          continue;
+        };
+        tile.column = column;
+        tile.row = row;
+        name.set(format!("Tile {} {}", column, row));
+        commands.entity(tile_entity).insert(Tween::new(
+          transform.translation,
+          cell_position((column, row)),
+          SWAP_SECONDS,
+          Easing::EaseOut,
+        ));
+      }
+      game_data.grid.swap(tile1, tile2);
+      swap_specials(&mut game_data.specials, tile1, tile2);
+      commands.entity(board).insert(ResolveBoard {
+        swap: Some((tile1, tile2)),
+        ..Default::default()
+      });
+      next_state.set(GameState::Resolving);
+      tile_drag_success_evw.send(TileDragSuccess { from: tile1, to: tile2 });
+    } else {
+      // Tiles that weren't dragged out of place get nudged towards each other first so the bounce shows.
+      let nudge = neighbour.map_or(Vec3::ZERO, |neighbour| (cell_position(neighbour) - cell_position(tile1)) * NUDGE);
+      for (tile_entity, tile, transform, _) in tiles.iter() {
+        let coord = (tile.column, tile.row);
+        let direction = if coord == tile1 {
+          1.0
+        } else if Some(coord) == neighbour {
+          -1.0
+        } else {
+          continue;
+        };
+        let home = cell_position(coord);
+        let start = if transform.translation == home { home + nudge * direction } else { transform.translation };
+        commands.entity(tile_entity).insert(Tween::new(start, home, BOUNCE_SECONDS, Easing::BackOut));
+      }
+    }
+  }
 }
   
 pub fn handle_tile_drag_move(
   mut tile_drag_move_evr: EventReader<TileDragMove>,
   game_config: Res<GameConfig>,
-  mut game_data: Query<(&mut TileIsDragging, &mut DragDirection)>,
-  mut swap_tile: Query<(&mut Transform, &Tile, Option<&SwapTile>, Option<&UnderSwapTile>), Or<(With<SwapTile>, With<UnderSwapTile>)>>,
+  mut game_data: Query<(&mut TileIsDragging, &mut DragDirection), Without<ResetDrag>>,
+  mut swap_tile: Query<(&mut Transform, &Tile, Option<&SwapTile>), DragTiles>,
 ) {
   for event in tile_drag_move_evr.iter() {
     
@@ -259,11 +290,11 @@ pub fn handle_tile_drag_move(
         Some(SwapDirection::Up) | Some(SwapDirection::Down) => drag_delta * Vec2::new(0.0, -1.0),
         None => Vec2::new(0.0, 0.0),
       };
-      for (mut transform, tile, swap_tile, under_swap_tile) in swap_tile.iter_mut() {
+      for (mut transform, tile, swap_tile) in swap_tile.iter_mut() {
         if swap_tile.is_some() {
           let flat =(Vec2::new(tile.column as f32, tile.row as f32) + drag_delta) * game_config.cell_size;
           transform.translation = Vec3::new(flat.x, flat.y, 2.0);            
-        } else if under_swap_tile.is_some() {
+        } else {
           let flat =(Vec2::new(tile.column as f32, tile.row as f32) + underswap_drag_delta) * game_config.cell_size;
           transform.translation = Vec3::new(flat.x, flat.y, 1.0);  
         }
@@ -294,18 +325,18 @@ pub fn handle_drag_direction_change(
   }
 }
 
+// Tiles that are already tweening, after a swap or a bounce, are left to finish on their own.
 pub fn reset_tiles(
-  mut tiles_to_reset: Query<(Entity, &Tile, &mut Transform), With<ResetTile>>,
+  tiles_to_reset: Query<(Entity, &Tile, &Transform, Option<&Tween>), With<ResetTile>>,
   game_config: Res<GameConfig>,
   mut commands: Commands,
 ) {
-  for (entity, tile, mut transform) in tiles_to_reset.iter_mut() {
+  for (entity, tile, transform, tween) in tiles_to_reset.iter() {
     println!("Resetting tile: {:?}", tile);
-    transform.translation = Vec3::new(
-      tile.column as f32 * game_config.cell_size,
-      tile.row as f32 * game_config.cell_size,
-      0.0,
-    );
+    let home = cell_to_local((tile.column, tile.row), game_config.cell_size);
+    if tween.is_none() && transform.translation != home {
+      commands.entity(entity).insert(Tween::new(transform.translation, home, RESET_SECONDS, Easing::EaseOut));
+    }
     commands.entity(entity)
       .remove::<ResetTile>()
       .remove::<SwapTile>()
@@ -313,6 +344,39 @@ pub fn reset_tiles(
   }
 }
 
+fn reset_dragging(
+  commands: &mut Commands,
+  dragging: &Query<Entity, With<TileIsDragging>>,
+  swap_tiles: &Query<Entity, DragTiles>,
+) {
+  for entity in dragging.iter() {
+    commands.entity(entity).insert(ResetDrag);
+  }
+  for entity in swap_tiles.iter() {
+    commands.entity(entity).insert(ResetTile);
+  }
+}
+
+pub fn handle_tile_drag_cancel(
+  mut commands: Commands,
+  mut tile_drag_cancel_evr: EventReader<TileDragCancel>,
+  dragging: Query<Entity, With<TileIsDragging>>,
+  swap_tiles: Query<Entity, DragTiles>,
+) {
+  if tile_drag_cancel_evr.iter().count() > 0 {
+    reset_dragging(&mut commands, &dragging, &swap_tiles);
+  }
+}
+
+// Drop whatever the player was dragging when the game is paused or the level ends mid-drag.
+pub fn cancel_drag(
+  mut commands: Commands,
+  dragging: Query<Entity, With<TileIsDragging>>,
+  swap_tiles: Query<Entity, DragTiles>,
+) {
+  reset_dragging(&mut commands, &dragging, &swap_tiles);
+}
+
 pub fn reset_drag(
   mut drag_to_reset: Query<Entity, With<ResetDrag>>,
   mut commands: Commands
@@ -327,32 +391,38 @@ pub fn reset_drag(
 }
 
 
-
-#[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
-pub struct GameEvents;
-
-#[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
-pub struct Cleanup;
-
-pub fn add_drag_to_app(app:&mut App) -> &mut App {
+// Dragging and the keyboard cursor both swap tiles through here, so this is added whichever of them is used.
+// The drag events are added here too, as other parts of the game listen for them even when dragging is off.
+pub fn add_swap_to_app(app: &mut App) -> &mut App {
   app
-    .register_type::<TileIsDragging>()
     .add_event::<TileDragStart>()
     .add_event::<TileDragMove>()
     .add_event::<TileDragCancel>()
     .add_event::<TileDragEnd>()
     .add_event::<TileDragSuccess>()
+    .add_event::<TileSwap>()
+    .configure_set(MouseInput.before(GameEvents).run_if(in_state(GameState::Playing)))
     .configure_set(
         GameEvents
           .before(Cleanup)
+          .run_if(in_state(GameState::Playing))
       )
+    .add_system(handle_tile_swap.in_set(GameEvents))
+}
+
+pub fn add_drag_to_app(app:&mut App) -> &mut App {
+  app
+    .register_type::<TileIsDragging>()
       .add_systems(
         (
           handle_tile_drag_start,
-          handle_tile_drag_end,
+          handle_tile_drag_cancel,
+          handle_tile_drag_end.before(handle_tile_swap),
           handle_tile_drag_move,
           handle_drag_direction_change
         ).in_set(GameEvents)
       ).add_systems((reset_tiles, reset_drag).in_set(Cleanup))
+      .add_system(cancel_drag.in_schedule(OnEnter(GameState::Paused)))
+      .add_system(cancel_drag.in_schedule(OnEnter(GameState::Results)))
 }
 
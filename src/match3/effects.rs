@@ -0,0 +1,185 @@
+use bevy::{prelude::*, render::render_resource::TextureFormat, utils::HashMap};
+
+use super::{components::*, grid::*, resolve::*, resources::*, shapes::*, system_sets::*};
+
+const CLEAR_SECONDS: f32 = 0.3;
+const PARTICLE_SECONDS: f32 = 0.5;
+const PARTICLES_PER_TILE: usize = 6;
+// Shapes bigger than a line of three get an extra burst where they were made
+const PARTICLES_PER_PIVOT: usize = 12;
+const PARTICLE_SIZE: f32 = 8.;
+const PARTICLE_GRAVITY: f32 = 600.;
+
+// The average colour of the mostly opaque pixels in an RGBA image, or None if it has none.
+pub fn average_color(rgba: &[u8]) -> Option<Color> {
+  let mut sum = [0u32; 3];
+  let mut count = 0;
+  for pixel in rgba.chunks_exact(4).filter(|pixel| pixel[3] >= 128) {
+    for (total, &channel) in sum.iter_mut().zip(pixel) {
+      *total += channel as u32;
+    }
+    count += 1;
+  }
+  if count == 0 {
+    return None;
+  }
+  let [r, g, b] = sum.map(|total| total as f32 / count as f32 / 255.0);
+  Some(Color::rgb(r, g, b))
+}
+
+// The main colour of a tile as it is drawn, its texture's colour times the tint from the tile set.
+// Just the tint until the texture has loaded.
+pub fn tile_color(tile_textures: &TileTextures, images: &Assets<Image>, tile_type: TileType) -> Color {
+  let tint = tile_textures.tint(tile_type);
+  let texture_color = images
+    .get(&tile_textures.get(tile_type))
+    .filter(|image| image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb)
+    .and_then(|image| average_color(&image.data))
+    .unwrap_or(Color::WHITE);
+  Color::rgb(texture_color.r() * tint.r(), texture_color.g() * tint.g(), texture_color.b() * tint.b())
+}
+
+// Velocities for a burst of particles, spread evenly around a circle.
+// The speed alternates so the burst doesn't look like a perfect ring.
+pub fn burst_velocities(count: usize, speed: f32) -> Vec<Vec2> {
+  (0..count)
+    .map(|index| {
+      let angle = index as f32 / count as f32 * std::f32::consts::TAU;
+      let speed = speed * [1.0, 0.7, 0.85][index % 3];
+      Vec2::new(angle.cos(), angle.sin()) * speed
+    })
+    .collect()
+}
+
+fn spawn_particles(commands: &mut Commands, board: Entity, center: Vec2, color: Color, count: usize, speed: f32) {
+  for velocity in burst_velocities(count, speed) {
+    let particle = commands.spawn((
+      SpriteBundle {
+        transform: Transform::from_xyz(center.x, center.y, 4.0),
+        sprite: Sprite {
+          color,
+          custom_size: Some(Vec2::new(PARTICLE_SIZE, PARTICLE_SIZE)),
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      Particle {
+        velocity,
+        timer: Timer::from_seconds(PARTICLE_SECONDS, TimerMode::Once),
+      },
+      Name::new("Particle"),
+    )).id();
+    commands.entity(board).add_child(particle);
+  }
+}
+
+// Tiles are despawned as soon as the grid clears them,
+// so a copy of each one is left behind to shrink away along with a burst of particles.
+pub fn spawn_clear_effects(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+  tile_textures: Res<TileTextures>,
+  images: Res<Assets<Image>>,
+  mut tiles_cleared_evr: EventReader<TilesCleared>,
+) {
+  let mut colors = HashMap::new();
+  let mut color_of = |tile_type| *colors.entry(tile_type).or_insert_with(|| tile_color(&tile_textures, &images, tile_type));
+  let cell_size = game_config.cell_size;
+  let cell_center = |(column, row): Coord| Vec2::new((column as f32 + 0.5) * cell_size, (row as f32 + 0.5) * cell_size);
+  for evt in tiles_cleared_evr.iter() {
+    // Each step of a cascade bursts a little harder than the one before
+    let speed = cell_size * (1.5 + 0.5 * evt.cascade as f32);
+    // Collected ingredients go away the same way
+    let ingredients = evt.collected.iter().map(|&coord| (coord, TileType::Ingredient));
+    for (coord, tile_type) in evt.tiles.iter().copied().chain(ingredients) {
+      let center = cell_center(coord);
+      let clearing_tile = commands.spawn((
+        SpriteBundle {
+          transform: Transform::from_xyz(center.x, center.y, 3.0),
+          texture: tile_textures.get(tile_type),
+          sprite: Sprite {
+            color: tile_textures.tint(tile_type),
+            custom_size: Some(Vec2::new(cell_size, cell_size)),
+            ..Default::default()
+          },
+          ..Default::default()
+        },
+        ClearingTile {
+          timer: Timer::from_seconds(CLEAR_SECONDS, TimerMode::Once),
+        },
+        Name::new(format!("Clearing {:?}", tile_type)),
+      )).id();
+      commands.entity(evt.board).add_child(clearing_tile);
+      spawn_particles(&mut commands, evt.board, center, color_of(tile_type), PARTICLES_PER_TILE, speed);
+    }
+    for shape in evt.shapes.iter().filter(|shape| shape.kind != ShapeKind::Line3) {
+      let color = evt
+        .tiles
+        .iter()
+        .find(|(coord, _)| *coord == shape.pivot)
+        .map_or(Color::WHITE, |&(_, tile_type)| color_of(tile_type));
+      spawn_particles(&mut commands, evt.board, cell_center(shape.pivot), color, PARTICLES_PER_PIVOT, speed * 2.);
+    }
+  }
+}
+
+pub fn animate_clearing_tiles(
+  mut commands: Commands,
+  time: Res<Time>,
+  mut clearing_tiles: Query<(Entity, &mut ClearingTile, &mut Transform, &mut Sprite)>,
+) {
+  for (entity, mut clearing_tile, mut transform, mut sprite) in clearing_tiles.iter_mut() {
+    clearing_tile.timer.tick(time.delta());
+    let remaining = 1.0 - clearing_tile.timer.percent();
+    transform.scale = Vec3::splat(remaining);
+    sprite.color.set_a(remaining);
+    if clearing_tile.timer.finished() {
+      commands.entity(entity).despawn_recursive();
+    }
+  }
+}
+
+pub fn animate_particles(
+  mut commands: Commands,
+  time: Res<Time>,
+  mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+  let delta = time.delta_seconds();
+  for (entity, mut particle, mut transform, mut sprite) in particles.iter_mut() {
+    particle.timer.tick(time.delta());
+    particle.velocity.y -= PARTICLE_GRAVITY * delta;
+    transform.translation += (particle.velocity * delta).extend(0.0);
+    sprite.color.set_a(1.0 - particle.timer.percent());
+    if particle.timer.finished() {
+      commands.entity(entity).despawn_recursive();
+    }
+  }
+}
+
+pub fn add_effects_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<ClearingTile>()
+    .register_type::<Particle>()
+    .add_system(spawn_clear_effects.after(Resolve).run_if(on_event::<TilesCleared>()))
+    .add_systems((animate_clearing_tiles, animate_particles).in_set(Animation))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn burst_velocities_spread_around_the_circle() {
+    let velocities = burst_velocities(4, 100.);
+    assert_eq!(velocities.len(), 4);
+    assert!((velocities[0] - Vec2::new(100., 0.)).length() < 1e-3);
+    assert!((velocities[2] + Vec2::new(85., 0.)).length() < 1e-3);
+    let sum = velocities.iter().fold(Vec2::ZERO, |sum, velocity| sum + velocity.normalize());
+    assert!(sum.length() < 1e-3);
+  }
+  #[test]
+  fn average_color_skips_transparent_pixels() {
+    let rgba = [255, 0, 0, 255, 0, 0, 255, 255, 0, 255, 0, 0];
+    assert_eq!(average_color(&rgba), Some(Color::rgb(0.5, 0.0, 0.5)));
+    assert_eq!(average_color(&[0, 255, 0, 0]), None);
+  }
+}
@@ -1,8 +1,11 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::{InspectorOptions, prelude::ReflectInspectorOptions};
-use rand::Rng;
+use rand::{Rng, seq::SliceRandom};
+use serde::Deserialize;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, InspectorOptions, FromReflect)]
+use super::{shapes::*, specials::*, tile_set::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, InspectorOptions, FromReflect, Deserialize)]
 
 #[reflect(Hash, InspectorOptions)]
 pub enum TileType {
@@ -11,45 +14,341 @@ pub enum TileType {
   Square,
   Circle,
   Diamond,
-  Star
+  Star,
+  Emerald,
+  // An item to bring down to the bottom of the board. It falls like a tile but never matches and can't be cleared.
+  Ingredient,
+}
+
+impl TileType {
+  pub fn is_matchable(self) -> bool {
+    self != TileType::Ingredient
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, FromReflect)]
+pub enum TileModifier {
+  // Clears its whole row
+  StripedHorizontal,
+  // Clears its whole column
+  StripedVertical,
+  // Clears the 3x3 area around it
+  Bomb,
+  // Clears every tile of one colour
+  ColorBomb,
+}
+
+#[derive(Reflect, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum SwapDirection {
+    Left,
+    Right,
+    Up,
+    Down,
 }
 
-pub fn rand_tile() -> TileType {
-  let mut rng = rand::thread_rng();
-  match rng.gen_range(0..6) {
-    0 => TileType::Pentagon,
-    1 => TileType::Triangle,
-    2 => TileType::Square,
-    3 => TileType::Circle,
-    4 => TileType::Diamond,
-    _ => TileType::Star,
+impl SwapDirection {
+  pub const ALL: [SwapDirection; 4] = [SwapDirection::Left, SwapDirection::Right, SwapDirection::Up, SwapDirection::Down];
+}
+
+// A cell on the board as (column, row). Column 0 is on the left and row 0 is at the bottom.
+pub type Coord = (usize, usize);
+
+// Which cells of a width x height rectangle are part of the board.
+// The rest are holes: they never hold a tile, they break up runs and tiles fall straight past them.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Reflect)]
+pub struct BoardMask {
+  width: usize,
+  height: usize,
+  cells: Vec<bool>,
+}
+
+impl BoardMask {
+  pub fn full(width: usize, height: usize) -> Self {
+    BoardMask {
+      width,
+      height,
+      cells: vec![true; width * height],
+    }
+  }
+
+  // Build a mask from rows of text listed from the top of the board down.
+  // '#' is a cell and anything else is a hole.
+  pub fn from_rows(rows: &[&str]) -> Self {
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    let mut mask = BoardMask {
+      width,
+      height: rows.len(),
+      cells: vec![false; width * rows.len()],
+    };
+    for (index, line) in rows.iter().enumerate() {
+      let row = rows.len() - 1 - index;
+      for (column, cell) in line.chars().enumerate() {
+        mask.cells[column * mask.height + row] = cell == '#';
+      }
+    }
+    mask
+  }
+
+  pub fn is_cell(&self, coord: Coord) -> bool {
+    coord.0 < self.width && coord.1 < self.height && self.cells[coord.0 * self.height + coord.1]
   }
 }
 
-pub fn create_grid(rows: usize, columns:usize) -> Vec<Vec<Option<TileType>>> {
-  loop {
+// The outlines that can be picked in the GameConfig.
+// The outline is fitted to the configured number of columns and rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect, FromReflect)]
+pub enum BoardLayout {
+  #[default]
+  Rectangle,
+  Diamond,
+  Heart,
+}
 
-    let mut grid = vec![vec![None; rows.into()]; columns.into()];
+impl BoardLayout {
+  pub fn mask(&self, columns: usize, rows: usize) -> BoardMask {
+    let mut mask = BoardMask::full(columns, rows);
+    if *self == BoardLayout::Rectangle {
+      return mask;
+    }
     for column in 0..columns {
       for row in 0..rows {
-        grid[column][row] = Some(rand_tile());
+        // The centre of the cell, with x running from -1 to 1 and y from 0 at the bottom to 1 at the top.
+        let x = (((column as f32 + 0.5) / columns as f32) * 2. - 1.).abs();
+        let y = (row as f32 + 0.5) / rows as f32;
+        mask.cells[column * rows + row] = match self {
+          BoardLayout::Rectangle => true,
+          BoardLayout::Diamond => x + (y * 2. - 1.).abs() <= 1.,
+          // A point at the bottom and two round lobes at the top
+          BoardLayout::Heart => if y < 0.6 {
+            x - 1. / columns as f32 <= y / 0.6
+          } else {
+            (x - 0.5).powi(2) + ((y - 0.6) / 0.8).powi(2) <= 0.25
+          },
+        };
       }
     }
-    loop {
-      let shapes = find_shapes(&grid);
-      if shapes.len() == 0 {
+    mask
+  }
+}
+
+// What else is in a cell besides its tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect, FromReflect)]
+pub struct Obstacles {
+  // Layers of ice under the tile. One layer breaks each time the tile on top is matched.
+  pub ice: u8,
+  // A chained tile can be matched, but it can't be swapped and it doesn't fall. The chain breaks with the tile.
+  pub chain: bool,
+  // A stone fills the cell instead of a tile. It never matches or falls, and breaks when a tile next to it is cleared.
+  pub stone: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Obstacle {
+  Ice,
+  Chain,
+  Stone,
+}
+
+// The board, stored column by column.
+// Reading or writing outside the board, or in one of its holes, is ignored rather than panicking.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Reflect)]
+pub struct Grid {
+  width: usize,
+  height: usize,
+  cells: Vec<Option<TileType>>,
+  obstacles: Vec<Obstacles>,
+  mask: BoardMask,
+}
+
+impl Grid {
+  #[cfg(test)]
+  pub fn new(width: usize, height: usize) -> Self {
+    Grid::with_mask(BoardMask::full(width, height))
+  }
+
+  pub fn with_mask(mask: BoardMask) -> Self {
+    Grid {
+      width: mask.width,
+      height: mask.height,
+      cells: vec![None; mask.width * mask.height],
+      obstacles: vec![Obstacles::default(); mask.width * mask.height],
+      mask,
+    }
+  }
+
+  // Build a grid from a list of columns, each listed from the bottom row up.
+  #[cfg(test)]
+  pub fn from_columns(columns: Vec<Vec<Option<TileType>>>) -> Self {
+    let height = columns.first().map_or(0, |column| column.len());
+    assert!(columns.iter().all(|column| column.len() == height), "all columns must be the same height");
+    let width = columns.len();
+    Grid {
+      width,
+      height,
+      cells: columns.into_iter().flatten().collect(),
+      obstacles: vec![Obstacles::default(); width * height],
+      mask: BoardMask::full(width, height),
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  // Whether the coordinate is on the board and not a hole.
+  pub fn is_cell(&self, coord: Coord) -> bool {
+    self.mask.is_cell(coord)
+  }
+
+  fn index(&self, coord: Coord) -> Option<usize> {
+    self.is_cell(coord).then(|| coord.0 * self.height + coord.1)
+  }
+
+  pub fn get(&self, coord: Coord) -> Option<TileType> {
+    self.index(coord).and_then(|index| self.cells[index])
+  }
+
+  pub fn set(&mut self, coord: Coord, tile: Option<TileType>) {
+    if let Some(index) = self.index(coord) {
+      self.cells[index] = tile;
+    }
+  }
+
+  pub fn take(&mut self, coord: Coord) -> Option<TileType> {
+    self.index(coord).and_then(|index| self.cells[index].take())
+  }
+
+  pub fn obstacles(&self, coord: Coord) -> Obstacles {
+    self.index(coord).map_or(Obstacles::default(), |index| self.obstacles[index])
+  }
+
+  pub fn set_obstacles(&mut self, coord: Coord, obstacles: Obstacles) {
+    if let Some(index) = self.index(coord) {
+      self.obstacles[index] = obstacles;
+    }
+  }
+
+  // Chained tiles and stones stay where they are: they can't be swapped, shuffled or moved by gravity.
+  pub fn is_locked(&self, coord: Coord) -> bool {
+    let obstacles = self.obstacles(coord);
+    obstacles.chain || obstacles.stone
+  }
+
+  // Whether the cell is waiting for a new tile. Stones fill their cell, so they never are.
+  pub fn is_empty(&self, coord: Coord) -> bool {
+    self.is_cell(coord) && self.get(coord).is_none() && !self.obstacles(coord).stone
+  }
+
+  // Every cell on the board, column by column from the bottom up. Holes are skipped.
+  pub fn coords(&self) -> impl Iterator<Item = Coord> + '_ {
+    let height = self.height;
+    (0..self.width)
+      .flat_map(move |column| (0..height).map(move |row| (column, row)))
+      .filter(|&coord| self.is_cell(coord))
+  }
+
+  // The cells of one column from the bottom up. Holes are listed as empty so they break up runs.
+  pub fn column(&self, column: usize) -> impl Iterator<Item = (Coord, Option<TileType>)> + '_ {
+    (0..self.height).map(move |row| ((column, row), self.get((column, row))))
+  }
+
+  // The cells of one row from left to right. Holes are listed as empty so they break up runs.
+  pub fn row(&self, row: usize) -> impl Iterator<Item = (Coord, Option<TileType>)> + '_ {
+    (0..self.width).map(move |column| ((column, row), self.get((column, row))))
+  }
+
+  pub fn neighbour(&self, coord: Coord, direction: SwapDirection) -> Option<Coord> {
+    let (column, row) = coord;
+    let neighbour = match direction {
+      SwapDirection::Left => (column.checked_sub(1)?, row),
+      SwapDirection::Right => (column + 1, row),
+      SwapDirection::Down => (column, row.checked_sub(1)?),
+      SwapDirection::Up => (column, row + 1),
+    };
+    self.is_cell(neighbour).then_some(neighbour)
+  }
+
+  pub fn neighbours(&self, coord: Coord) -> impl Iterator<Item = (SwapDirection, Coord)> + '_ {
+    SwapDirection::ALL
+      .into_iter()
+      .filter_map(move |direction| self.neighbour(coord, direction).map(|neighbour| (direction, neighbour)))
+  }
+
+  // Swap the tiles in two cells. Returns false, leaving the grid alone, if either is off the board, a hole or locked.
+  // Obstacles belong to the cell and stay where they are.
+  pub fn swap(&mut self, tile1: Coord, tile2: Coord) -> bool {
+    match (self.index(tile1), self.index(tile2)) {
+      (Some(index1), Some(index2)) if !self.is_locked(tile1) && !self.is_locked(tile2) => {
+        self.cells.swap(index1, index2);
+        true
+      }
+      _ => false,
+    }
+  }
+}
+
+pub fn create_grid(mask: &BoardMask, tile_set: &TileSet, rng: &mut impl Rng) -> Grid {
+  fill_grid(&Grid::with_mask(mask.clone()), tile_set, rng)
+}
+
+// How many times the random tiles are picked again before fill_grid gives up
+const MAX_FILL_ATTEMPTS: usize = 100;
+
+// Fill the empty cells of a template with random tiles, so the board starts with no shapes and at least one possible swap.
+// The template's own tiles are kept, and levels are checked so those never make a shape between themselves.
+// Random tiles can nearly always be picked around them, but if no fill passes after enough attempts the last one is handed back.
+pub fn fill_grid(template: &Grid, tile_set: &TileSet, rng: &mut impl Rng) -> Grid {
+  let empty = template.coords().filter(|&coord| template.is_empty(coord)).collect::<Vec<_>>();
+  let mut grid = template.clone();
+  for _ in 0..MAX_FILL_ATTEMPTS {
+    grid = template.clone();
+    for &coord in empty.iter() {
+      grid.set(coord, Some(tile_set.random(rng)));
+    }
+    for _ in 0..MAX_FILL_ATTEMPTS {
+      let rerolled = find_shapes(&grid)
+        .into_iter()
+        .flatten()
+        .filter(|&coord| template.is_empty(coord))
+        .collect::<Vec<_>>();
+      if rerolled.is_empty() {
         break;
       }
-      for shape in shapes {
-        for (column, row) in shape {
-          grid[column][row] = Some(rand_tile());
-        }
+      for coord in rerolled {
+        grid.set(coord, Some(tile_set.random(rng)));
       }
     }
-    if has_possible_swaps(&mut grid) {
-      return grid;
+    if find_shapes(&grid).is_empty() && has_possible_swaps(&mut grid) {
+      break;
     }
   }
+  grid
+}
+
+// Find the runs of 3 or more tiles of the same type along one line of cells.
+fn find_runs(line: impl Iterator<Item = (Coord, Option<TileType>)>) -> Vec<Vec<Coord>> {
+  let mut runs = vec![];
+  let mut run: Vec<Coord> = vec![];
+  let mut run_type = None;
+  for (coord, tile_type) in line {
+    if tile_type.is_some_and(TileType::is_matchable) && tile_type == run_type {
+      run.push(coord);
+      continue;
+    }
+    if run.len() >= 3 {
+      runs.push(run);
+    }
+    run = vec![coord];
+    run_type = tile_type;
+  }
+  if run.len() >= 3 {
+    runs.push(run);
+  }
+  runs
 }
 
 // Given a grid, find all the shapes in it
@@ -58,165 +357,364 @@ pub fn create_grid(rows: usize, columns:usize) -> Vec<Vec<Option<TileType>>> {
 // A horizontal shape and vertical shape can share a tile, 
 // but no two vertical shapes should share the same tile,
 // and no two horizontal shapes should share the same tile.
-pub fn find_shapes(grid: &Vec<Vec<Option<TileType>>>) -> Vec<Vec<(usize, usize)>> {
+// Vertical shapes are listed first, column by column, then horizontal shapes row by row.
+pub fn find_shapes(grid: &Grid) -> Vec<Vec<Coord>> {
   let mut shapes = vec![];
-  // find horizontal shapes.
-  // for each row in the grid, start at the first tile.
-  // check to see if that tile is not None.  If so, move to the next.
-  // if it is Some, check to see if the next tile is Some and the same type.
-  // Find out how long the shape is, and push the coordinates of the tiles in the shape to the shapes vector.
-  // Move to the next tile after the shape.
-  // Repeat until the end of the row.
-  // Repeat for each row.
-  for row in 0..grid.len() {
-    let mut column = 0;
-    while column < grid[row].len() {
-      if let Some(tile_type) = grid[row][column] {
-        let mut shape = vec![(row, column)];
-        let mut next_column = column + 1;
-        while next_column < grid[row].len() && grid[row][next_column] == Some(tile_type) {
-          shape.push((row, next_column));
-          next_column += 1;
-        }
-        if shape.len() >= 3 {
-          shapes.push(shape);
-        }
-        column = next_column;
-      } else {
-        column += 1;
-      }
-    }
+  for column in 0..grid.width() {
+    shapes.extend(find_runs(grid.column(column)));
   }
-  // find vertical shapes the same way.
-  for column in 0..grid[0].len() {
-    let mut row = 0;
-    while row < grid.len() {
-      if let Some(tile_type) = grid[row][column] {
-        let mut shape = vec![(row, column)];
-        let mut next_row = row + 1;
-        while next_row < grid.len() && grid[next_row][column] == Some(tile_type) {
-          shape.push((next_row, column));
-          next_row += 1;
-        }
-        if shape.len() >= 3 {
-          shapes.push(shape);
-        }
-        row = next_row;
-      } else {
-        row += 1;
-      }
-    }
+  for row in 0..grid.height() {
+    shapes.extend(find_runs(grid.row(row)));
   }
   shapes
 }
 
-pub fn has_shape(grid: &Vec<Vec<Option<TileType>>>) -> bool {
-  if grid.len() < 3 || grid[0].len() < 3 {
+pub fn has_shape(grid: &Grid) -> bool {
+  grid.coords().any(|(column, row)| {
+    let tile_type = grid.get((column, row));
+    tile_type.is_some_and(TileType::is_matchable) && (
+      (row >= 2 && grid.get((column, row - 1)) == tile_type && grid.get((column, row - 2)) == tile_type)
+        || (column >= 2 && grid.get((column - 1, row)) == tile_type && grid.get((column - 2, row)) == tile_type)
+    )
+  })
+}
+
+pub fn can_swap(grid: &mut Grid, tile1: Coord, tile2: Coord) -> bool {
+  if !grid.swap(tile1, tile2) {
     return false;
   }
-  for col in 0..grid.len() {
-    let column = &grid[col];
-    for row in 2..column.len() {
-      let tile_type = column[row];
-      if tile_type == None {
-        continue;
-      }
-      if column[row - 1] == tile_type && column[row - 2] == tile_type {
-        return true;
+  let result = has_shape(grid);
+  grid.swap(tile1, tile2);
+  result
+}
+
+pub fn has_possible_swaps(grid: &mut Grid) -> bool {
+  // For each tile in the grid, check to see if swapping it with the tile to the right or above it results in a shape.
+  // If so, return true.
+  // If not, return false.
+  for coord in grid.coords().collect::<Vec<_>>() {
+    for direction in [SwapDirection::Right, SwapDirection::Up] {
+      if let Some(neighbour) = grid.neighbour(coord, direction) {
+        if can_swap(grid, coord, neighbour) {
+          return true;
+        }
       }
     }
   }
-  for row in 0..grid[0].len() {
-    for col in 2..grid.len() {
-      let tile_type = grid[col][row];
-      if tile_type == None {
-        continue;
-      }
-      if grid[col - 1][row] == tile_type && grid[col - 2][row] == tile_type {
-        return true;
+  false
+}
+
+const MAX_SHUFFLE_ATTEMPTS: usize = 100;
+
+// The player can still move if a swap makes a shape or sets off a special tile.
+pub fn has_moves(grid: &mut Grid, specials: &Specials) -> bool {
+  has_special_moves(grid, specials) || has_possible_swaps(grid)
+}
+
+// Rearrange the tiles already on the grid until there are no shapes and at least one possible move.
+// Special tiles move with their tile and locked tiles stay where they are.
+// Returns the moves made, or None if no such arrangement was found.
+pub fn shuffle_grid(grid: &mut Grid, specials: &mut Specials, rng: &mut impl Rng) -> Option<Vec<TileMove>> {
+  let coords = grid
+    .coords()
+    .filter(|&coord| grid.get(coord).is_some() && !grid.is_locked(coord))
+    .collect::<Vec<_>>();
+  let mut sources = coords.clone();
+  for _ in 0..MAX_SHUFFLE_ATTEMPTS {
+    sources.shuffle(rng);
+    let mut shuffled = grid.clone();
+    let mut shuffled_specials = Specials::default();
+    for (&to, &from) in coords.iter().zip(sources.iter()) {
+      shuffled.set(to, grid.get(from));
+      if let Some(&modifier) = specials.get(&from) {
+        shuffled_specials.insert(to, modifier);
       }
     }
+    if !has_shape(&shuffled) && has_moves(&mut shuffled, &shuffled_specials) {
+      *grid = shuffled;
+      *specials = shuffled_specials;
+      return Some(
+        coords
+          .iter()
+          .zip(sources.iter())
+          .filter(|(to, from)| to != from)
+          .map(|(&to, &from)| TileMove { from, to })
+          .collect(),
+      );
+    }
   }
+  None
+}
 
-  return false;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileMove {
+  pub from: Coord,
+  pub to: Coord,
 }
 
-pub fn can_swap(grid: &mut Vec<Vec<Option<TileType>>>, tile1: (usize, usize), tile2: (usize, usize)) -> bool {
-  let tile1_type = grid[tile1.0][tile1.1];
-  let tile2_type = grid[tile2.0][tile2.1];
-  grid[tile1.0][tile1.1] = tile2_type;
-  grid[tile2.0][tile2.1] = tile1_type;
-  let result = has_shape(grid);
-  grid[tile1.0][tile1.1] = tile1_type;
-  grid[tile2.0][tile2.1] = tile2_type;
-  result
+// One pass of the resolve loop: the ingredients that were collected, the shapes that were matched,
+// the special tiles that went off or were created, the tiles that were removed, the obstacles that broke, the tiles that fell and the tiles that were added at the top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveStep {
+  pub shapes: Vec<Shape>,
+  pub triggered: Vec<(Coord, TileModifier)>,
+  pub created: Vec<(Coord, TileModifier)>,
+  pub removed: Vec<(Coord, TileType)>,
+  pub broken: Vec<(Coord, Obstacle)>,
+  pub collected: Vec<Coord>,
+  pub drops: Vec<TileMove>,
+  pub spawned: Vec<(Coord, TileType)>,
 }
 
-pub fn swap_tiles(grid: &mut Vec<Vec<Option<TileType>>>, tile1: (usize, usize), tile2: (usize, usize)) -> bool {
-  // Swap tiles in the grid at tile1 and tile2
-  // If the swap results in a shape, return true
-  // If not, swap the tiles back and return false
-  let tile1_type = grid[tile1.0][tile1.1];
-  let tile2_type = grid[tile2.0][tile2.1];
-  grid[tile1.0][tile1.1] = tile2_type;
-  grid[tile2.0][tile2.1] = tile1_type;
-  if has_shape(grid) {
-    true
-  } else {
-    grid[tile1.0][tile1.1] = tile1_type;
-    grid[tile2.0][tile2.1] = tile2_type;
-    false
+// Remove the tiles at the given coordinates from the grid. Ingredients are left where they are.
+// Each coordinate is only reported once, even if it is listed more than once.
+pub fn remove_tiles(grid: &mut Grid, coords: &[Coord]) -> Vec<(Coord, TileType)> {
+  let mut removed = vec![];
+  for &coord in coords {
+    if grid.get(coord).is_some_and(TileType::is_matchable) {
+      removed.extend(grid.take(coord).map(|tile_type| (coord, tile_type)));
+    }
   }
+  removed
 }
 
-pub fn has_possible_swaps(grid: &mut Vec<Vec<Option<TileType>>>) -> bool {
-  // For each tile in the grid, check to see if swapping it with the tile to the right or below it results in a shape.
-  // If so, return true.
-  // If not, return false.
-  for row in 0..grid.len() {
-    for col in 0..grid[row].len() {
-      if col < grid[row].len() - 1  && can_swap(grid, (row, col), (row, col + 1)) {
-        return true;
+// The rows of a column that tiles can fall through, from the bottom up, split into segments at locked cells.
+// Tiles never fall past a chained tile or a stone, but fall straight past holes.
+// The last segment is the one open to the top of the board, and may be empty.
+fn column_segments(grid: &Grid, column: usize) -> Vec<Vec<usize>> {
+  let mut segments = vec![vec![]];
+  for row in (0..grid.height()).filter(|&row| grid.is_cell((column, row))) {
+    if grid.is_locked((column, row)) {
+      segments.push(vec![]);
+    } else if let Some(segment) = segments.last_mut() {
+      segment.push(row);
+    }
+  }
+  segments
+}
+
+// The ingredients sitting in the bottom cell of their column, ready to be collected.
+// An ingredient held up by a chained tile or a stone hasn't reached the bottom, and one can't be collected from a locked cell.
+pub fn ingredients_at_bottom(grid: &Grid) -> Vec<Coord> {
+  (0..grid.width())
+    .filter_map(|column| (0..grid.height()).map(|row| (column, row)).find(|&coord| grid.is_cell(coord)))
+    .filter(|&coord| grid.get(coord) == Some(TileType::Ingredient) && !grid.is_locked(coord))
+    .collect()
+}
+
+pub fn collect_ingredients(grid: &mut Grid) -> Vec<Coord> {
+  let collected = ingredients_at_bottom(grid);
+  for &coord in collected.iter() {
+    grid.take(coord);
+  }
+  collected
+}
+
+// Break the obstacles touched by the tiles that were just removed.
+// A removed tile takes one layer of ice and its chain with it, and breaks any stone next to it.
+// Stones in the cleared cells themselves, hit by a special tile, break too.
+pub fn break_obstacles(grid: &mut Grid, removed: &[(Coord, TileType)], cleared: &[Coord]) -> Vec<(Coord, Obstacle)> {
+  let mut broken = vec![];
+  for &(coord, _) in removed {
+    let mut obstacles = grid.obstacles(coord);
+    if obstacles.ice > 0 {
+      obstacles.ice -= 1;
+      broken.push((coord, Obstacle::Ice));
+    }
+    if obstacles.chain {
+      obstacles.chain = false;
+      broken.push((coord, Obstacle::Chain));
+    }
+    grid.set_obstacles(coord, obstacles);
+  }
+  let stones = removed
+    .iter()
+    .flat_map(|&(coord, _)| grid.neighbours(coord).map(|(_, neighbour)| neighbour))
+    .chain(cleared.iter().copied())
+    .collect::<Vec<_>>();
+  for coord in stones {
+    let mut obstacles = grid.obstacles(coord);
+    if obstacles.stone {
+      obstacles.stone = false;
+      grid.set_obstacles(coord, obstacles);
+      broken.push((coord, Obstacle::Stone));
+    }
+  }
+  broken
+}
+
+// Let the tiles in each column fall down into the empty cells below them.
+// Row 0 is the bottom of the board. Tiles fall straight past holes to the next cell below,
+// but stop on chained tiles and stones, which stay where they are.
+pub fn apply_gravity(grid: &mut Grid) -> Vec<TileMove> {
+  let mut drops = vec![];
+  for column in 0..grid.width() {
+    for rows in column_segments(grid, column) {
+      let mut next_row = 0;
+      for &row in rows.iter() {
+        if let Some(tile_type) = grid.get((column, row)) {
+          let to = rows[next_row];
+          if row != to {
+            grid.set((column, to), Some(tile_type));
+            grid.set((column, row), None);
+            drops.push(TileMove { from: (column, row), to: (column, to) });
+          }
+          next_row += 1;
+        }
       }
-      if row < grid.len() - 1 && can_swap(grid, (row, col), (row + 1, col)) {
-        return true;
+    }
+  }
+  drops
+}
+
+// Fill the empty cells that are open to the top of the board with new random tiles from the tile set.
+// Cells under a chained tile or a stone stay empty until it breaks.
+pub fn refill_grid(grid: &mut Grid, tile_set: &TileSet, rng: &mut impl Rng) -> Vec<(Coord, TileType)> {
+  let mut spawned = vec![];
+  for column in 0..grid.width() {
+    let open_rows = column_segments(grid, column).pop().unwrap_or_default();
+    for coord in open_rows.into_iter().map(|row| (column, row)) {
+      if !grid.is_empty(coord) {
+        continue;
       }
+      let tile_type = tile_set.random(rng);
+      grid.set(coord, Some(tile_type));
+      spawned.push((coord, tile_type));
     }
   }
-  false
+  spawned
+}
+
+// Collect the ingredients that have reached the bottom, clear the shapes currently on the grid along with anything
+// the special tiles in them clear, turn big shapes into special tiles, drop the tiles above and refill from the top.
+// An ingredient that falls to the bottom is collected by the next step, once it has been seen to land.
+// swapped holds the tiles the player just swapped, so new special tiles appear where the player made the match.
+// Returns None if there was nothing to collect or clear.
+pub fn resolve_step(
+  grid: &mut Grid,
+  specials: &mut Specials,
+  swapped: &[Coord],
+  activation: Option<Activation>,
+  tile_set: &TileSet,
+  rng: &mut impl Rng,
+) -> Option<ResolveStep> {
+  let collected = collect_ingredients(grid);
+  let runs = find_shapes(grid);
+  if collected.is_empty() && runs.is_empty() && activation.is_none() {
+    return None;
+  }
+  let shapes = classify_shapes(&runs);
+  let created = shapes
+    .iter()
+    .filter_map(|shape| special_for_shape(shape).map(|modifier| (special_location(shape, swapped), modifier)))
+    .collect::<Vec<_>>();
+
+  let mut cleared = runs.into_iter().flatten().collect::<Vec<_>>();
+  let mut triggered = vec![];
+  if let Some(activation) = activation {
+    cleared.extend(activation.cleared);
+    triggered.extend(activation.triggered);
+  }
+  let (cleared, chained) = expand_cleared(grid, specials, cleared);
+  triggered.extend(chained);
+
+  let cleared = cleared
+    .into_iter()
+    .filter(|coord| !created.iter().any(|(created_coord, _)| created_coord == coord))
+    .collect::<Vec<_>>();
+  for coord in cleared.iter() {
+    specials.remove(coord);
+  }
+  let removed = remove_tiles(grid, &cleared);
+  let broken = break_obstacles(grid, &removed, &cleared);
+  for &(coord, modifier) in created.iter() {
+    specials.insert(coord, modifier);
+  }
+
+  let drops = apply_gravity(grid);
+  move_specials(specials, &drops);
+  let spawned = refill_grid(grid, tile_set, rng);
+  Some(ResolveStep { shapes, triggered, created, removed, broken, collected, drops, spawned })
+}
+
+// The next step of a resolve. swap holds the player's swap for the first step, which may set off a special tile,
+// and is None for the cascade after it. The swap has already been applied to the grid and the specials.
+pub fn resolve_next_step(
+  grid: &mut Grid,
+  specials: &mut Specials,
+  swap: Option<(Coord, Coord)>,
+  tile_set: &TileSet,
+  rng: &mut impl Rng,
+) -> Option<ResolveStep> {
+  match swap {
+    Some((tile1, tile2)) => {
+      let activation = activate_swap(grid, specials, tile1, tile2);
+      resolve_step(grid, specials, &[tile1, tile2], activation, tile_set, rng)
+    }
+    None => resolve_step(grid, specials, &[], None, tile_set, rng),
+  }
+}
+
+// The game resolves one step at a time so each can be animated. These run every step in one go.
+#[cfg(test)]
+pub fn resolve_swap(
+  grid: &mut Grid,
+  specials: &mut Specials,
+  tile1: Coord,
+  tile2: Coord,
+  tile_set: &TileSet,
+  rng: &mut impl Rng,
+) -> Vec<ResolveStep> {
+  let mut steps = vec![];
+  steps.extend(resolve_next_step(grid, specials, Some((tile1, tile2)), tile_set, rng));
+  steps.extend(resolve_grid(grid, specials, tile_set, rng));
+  steps
+}
+
+#[cfg(test)]
+pub fn resolve_grid(grid: &mut Grid, specials: &mut Specials, tile_set: &TileSet, rng: &mut impl Rng) -> Vec<ResolveStep> {
+  let mut steps = vec![];
+  while let Some(step) = resolve_step(grid, specials, &[], None, tile_set, rng) {
+    steps.push(step);
+  }
+  steps
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use rand::{SeedableRng, rngs::StdRng};
+  fn column(grid: &Grid, column: usize) -> Vec<Option<TileType>> {
+    grid.column(column).map(|(_, tile)| tile).collect()
+  }
   #[test]
   fn find_shapes_returns_empty_if_no_shapes_found() {
-    let grid = vec![
+    let grid = Grid::from_columns(vec![
         vec![Some(TileType::Pentagon), Some(TileType::Circle), Some(TileType::Circle)],
         vec![Some(TileType::Circle), Some(TileType::Pentagon), Some(TileType::Circle)],
         vec![Some(TileType::Circle), Some(TileType::Circle), Some(TileType::Pentagon)],
-    ];
+    ]);
     let shapes = find_shapes(&grid);
     assert_eq!(shapes.len(), 0);
   }
   #[test]
-  fn find_shapes_finds_horizontal_shapes() {
-    let grid = vec![
+  fn find_shapes_finds_vertical_shapes() {
+    let grid = Grid::from_columns(vec![
       vec![Some(TileType::Pentagon), Some(TileType::Pentagon), Some(TileType::Pentagon)],
       vec![Some(TileType::Circle), Some(TileType::Pentagon), Some(TileType::Circle)],
       vec![Some(TileType::Circle), Some(TileType::Circle), Some(TileType::Pentagon)],
-    ];
+    ]);
     let shapes = find_shapes(&grid);
     assert_eq!(shapes.len(), 1);
     assert_eq!(shapes[0], vec![(0, 0), (0, 1), (0, 2)]);
   }
   #[test]
-  fn find_shapes_find_vertical_shapes() {
-    let grid = vec![
+  fn find_shapes_finds_horizontal_shapes() {
+    let grid = Grid::from_columns(vec![
       vec![Some(TileType::Pentagon), Some(TileType::Circle), Some(TileType::Circle)],
       vec![Some(TileType::Pentagon), Some(TileType::Pentagon), Some(TileType::Circle)],
       vec![Some(TileType::Pentagon), Some(TileType::Circle), Some(TileType::Pentagon)],
-    ];
+    ]);
     let shapes = find_shapes(&grid);
     assert_eq!(shapes.len(), 1);
     assert_eq!(shapes[0], vec![(0, 0), (1, 0), (2, 0)]);
@@ -226,12 +724,12 @@ mod tests {
     // make a grid of 4x4 with all Some(TileType::Circle) 
     // except for one row of Some(TileType::Pentagon)
     // and one column of Some(TileType::Pentagon)
-    let mut grid = vec![vec![Some(TileType::Circle); 4]; 4];
-    for column in 0..4 {
-      grid[2][column] = Some(TileType::Pentagon);
-    }
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 4]; 4]);
     for row in 0..4 {
-      grid[row][2] = Some(TileType::Pentagon);
+      grid.set((2, row), Some(TileType::Pentagon));
+    }
+    for column in 0..4 {
+      grid.set((column, 2), Some(TileType::Pentagon));
     }
     let shapes = find_shapes(&grid);
     assert_eq!(shapes.len(), 2);
@@ -239,5 +737,403 @@ mod tests {
     assert_eq!(shapes[1], vec![(0, 2), (1, 2), (2, 2), (3, 2)]);
 
   }
+  #[test]
+  fn remove_tiles_clears_shared_tiles_once() {
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 3]; 3]);
+    let coords = vec![(0, 0), (0, 1), (0, 2), (0, 0), (1, 0), (2, 0)];
+    let removed = remove_tiles(&mut grid, &coords);
+    assert_eq!(removed.iter().map(|(coord, _)| *coord).collect::<Vec<_>>(), vec![(0, 0), (0, 1), (0, 2), (1, 0), (2, 0)]);
+    assert!(removed.iter().all(|(_, tile_type)| *tile_type == TileType::Circle));
+    assert_eq!(column(&grid, 0), vec![None, None, None]);
+    assert_eq!(column(&grid, 1), vec![None, Some(TileType::Circle), Some(TileType::Circle)]);
+  }
+  #[test]
+  fn apply_gravity_drops_tiles_into_gaps() {
+    let mut grid = Grid::from_columns(vec![
+      vec![None, Some(TileType::Star), None, Some(TileType::Circle)],
+      vec![Some(TileType::Square), Some(TileType::Diamond), None, None],
+    ]);
+    let drops = apply_gravity(&mut grid);
+    assert_eq!(column(&grid, 0), vec![Some(TileType::Star), Some(TileType::Circle), None, None]);
+    assert_eq!(column(&grid, 1), vec![Some(TileType::Square), Some(TileType::Diamond), None, None]);
+    assert_eq!(drops, vec![
+      TileMove { from: (0, 1), to: (0, 0) },
+      TileMove { from: (0, 3), to: (0, 1) },
+    ]);
+  }
+  #[test]
+  fn resolve_grid_leaves_a_full_grid_without_shapes() {
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 4]; 4]);
+    let mut specials = Specials::default();
+    let steps = resolve_grid(&mut grid, &mut specials, &TileSet::default(), &mut StdRng::seed_from_u64(1));
+    assert!(!steps.is_empty());
+    assert_eq!(steps[0].created, vec![((1, 1), TileModifier::Bomb)]);
+    assert_eq!(steps[0].removed.len(), 15);
+    assert!(grid.coords().all(|coord| grid.get(coord).is_some()));
+    assert!(find_shapes(&grid).is_empty());
+  }
+  #[test]
+  fn resolve_grid_does_nothing_without_shapes() {
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Pentagon), Some(TileType::Circle), Some(TileType::Circle)],
+      vec![Some(TileType::Circle), Some(TileType::Pentagon), Some(TileType::Circle)],
+      vec![Some(TileType::Circle), Some(TileType::Circle), Some(TileType::Pentagon)],
+    ]);
+    let before = grid.clone();
+    assert!(resolve_grid(&mut grid, &mut Specials::default(), &TileSet::default(), &mut StdRng::seed_from_u64(1)).is_empty());
+    assert_eq!(grid, before);
+  }
+  #[test]
+  fn resolve_swap_creates_special_at_the_swapped_tile() {
+    // Column 0 from the bottom: Circle Circle Star Circle, and a Circle at (1, 2) to swap in.
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Circle), Some(TileType::Star), Some(TileType::Circle)],
+      vec![Some(TileType::Square), Some(TileType::Diamond), Some(TileType::Circle), Some(TileType::Square)],
+      vec![Some(TileType::Diamond), Some(TileType::Square), Some(TileType::Diamond), Some(TileType::Pentagon)],
+    ]);
+    let mut specials = Specials::default();
+    grid.swap((0, 2), (1, 2));
+    let steps = resolve_swap(&mut grid, &mut specials, (1, 2), (0, 2), &TileSet::default(), &mut StdRng::seed_from_u64(1));
+    assert_eq!(steps[0].created, vec![((0, 2), TileModifier::StripedVertical)]);
+    assert_eq!(steps[0].removed.iter().map(|(coord, _)| *coord).collect::<Vec<_>>(), vec![(0, 0), (0, 1), (0, 3)]);
+    assert_eq!(steps[0].drops, vec![TileMove { from: (0, 2), to: (0, 0) }]);
+  }
+  #[test]
+  fn resolve_step_sets_off_special_tiles_in_a_shape() {
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Star), Some(TileType::Square)],
+      vec![Some(TileType::Circle), Some(TileType::Diamond), Some(TileType::Pentagon)],
+      vec![Some(TileType::Circle), Some(TileType::Star), Some(TileType::Square)],
+    ]);
+    let mut specials = Specials::default();
+    specials.insert((1, 0), TileModifier::StripedVertical);
+    let step = resolve_step(&mut grid, &mut specials, &[], None, &TileSet::default(), &mut StdRng::seed_from_u64(1)).unwrap();
+    assert_eq!(step.triggered, vec![((1, 0), TileModifier::StripedVertical)]);
+    assert_eq!(step.removed.iter().map(|(coord, _)| *coord).collect::<Vec<_>>(), vec![(0, 0), (1, 0), (2, 0), (1, 1), (1, 2)]);
+    assert!(specials.is_empty());
+  }
+  #[test]
+  fn has_moves_is_false_on_a_dead_board() {
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Pentagon), Some(TileType::Circle), Some(TileType::Star)],
+      vec![Some(TileType::Square), Some(TileType::Diamond), Some(TileType::Triangle)],
+      vec![Some(TileType::Pentagon), Some(TileType::Circle), Some(TileType::Star)],
+    ]);
+    let mut specials = Specials::default();
+    assert!(!has_moves(&mut grid, &specials));
+    specials.insert((1, 1), TileModifier::ColorBomb);
+    assert!(has_moves(&mut grid, &specials));
+  }
+  #[test]
+  fn shuffle_grid_keeps_the_tiles_and_leaves_a_move() {
+    let types = [TileType::Pentagon, TileType::Circle, TileType::Star, TileType::Square, TileType::Diamond];
+    let mut grid = Grid::from_columns((0..5)
+      .map(|column| (0..5).map(|row| Some(types[(column + row) % 5])).collect::<Vec<_>>())
+      .collect::<Vec<_>>());
+    let mut specials = Specials::default();
+    specials.insert((0, 0), TileModifier::Bomb);
+    let before = grid.clone();
+    let moves = shuffle_grid(&mut grid, &mut specials, &mut StdRng::seed_from_u64(1)).unwrap();
+    assert!(find_shapes(&grid).is_empty());
+    assert!(has_moves(&mut grid, &specials));
+    for tile_move in moves.iter() {
+      assert_eq!(grid.get(tile_move.to), before.get(tile_move.from));
+    }
+    for tile_type in types {
+      let count = grid.coords().filter(|&coord| grid.get(coord) == Some(tile_type)).count();
+      assert_eq!(count, 5);
+    }
+    assert_eq!(specials.len(), 1);
+  }
+  #[test]
+  fn the_same_seed_gives_the_same_game() {
+    let play = |seed: u64| {
+      let mut rng = StdRng::seed_from_u64(seed);
+      let mut grid = create_grid(&BoardMask::full(6, 6), &TileSet::default(), &mut rng);
+      let start = grid.clone();
+      let mut specials = Specials::default();
+      let steps = resolve_grid(&mut Grid::from_columns(vec![vec![Some(TileType::Circle); 6]; 6]), &mut specials, &TileSet::default(), &mut rng);
+      let shuffled = shuffle_grid(&mut grid, &mut specials, &mut rng);
+      (start, steps, shuffled, grid)
+    };
+    assert_eq!(play(42), play(42));
+    assert_ne!(play(42).0, play(43).0);
+  }
+  #[test]
+  fn create_grid_fills_a_wide_board() {
+    let grid = create_grid(&BoardMask::full(7, 4), &TileSet::default(), &mut StdRng::seed_from_u64(3));
+    assert_eq!((grid.width(), grid.height()), (7, 4));
+    assert!(grid.coords().all(|coord| grid.get(coord).is_some()));
+    assert!(find_shapes(&grid).is_empty());
+  }
+  #[test]
+  fn create_grid_and_refills_stay_in_the_tile_set() {
+    for colors in [4, 5, 7] {
+      let tile_set = TileSet::with_colors(colors);
+      let mut rng = StdRng::seed_from_u64(colors as u64);
+      let mut grid = create_grid(&BoardMask::full(6, 6), &tile_set, &mut rng);
+      assert!(grid.coords().all(|coord| tile_set.get(grid.get(coord).unwrap()).is_some()));
+      remove_tiles(&mut grid, &[(0, 0), (3, 5)]);
+      let spawned = refill_grid(&mut grid, &tile_set, &mut rng);
+      assert!(spawned.iter().all(|&(_, tile_type)| tile_set.get(tile_type).is_some()));
+    }
+  }
+  #[test]
+  fn fill_grid_keeps_the_template_tiles() {
+    let mut template = Grid::new(5, 5);
+    template.set((0, 0), Some(TileType::Star));
+    template.set((1, 0), Some(TileType::Star));
+    template.set((4, 4), Some(TileType::Circle));
+    let grid = fill_grid(&template, &TileSet::default(), &mut StdRng::seed_from_u64(4));
+    assert_eq!(grid.get((0, 0)), Some(TileType::Star));
+    assert_eq!(grid.get((1, 0)), Some(TileType::Star));
+    assert_eq!(grid.get((4, 4)), Some(TileType::Circle));
+    assert!(grid.coords().all(|coord| grid.get(coord).is_some()));
+    assert!(find_shapes(&grid).is_empty());
+  }
+  #[test]
+  fn find_shapes_works_on_wide_and_tall_boards() {
+    // Five columns of three rows, with a run along the top row.
+    let mut wide = Grid::new(5, 3);
+    for (index, coord) in wide.coords().collect::<Vec<_>>().into_iter().enumerate() {
+      wide.set(coord, Some(if index % 2 == 0 { TileType::Square } else { TileType::Diamond }));
+    }
+    for column in 1..4 {
+      wide.set((column, 2), Some(TileType::Star));
+    }
+    assert_eq!(find_shapes(&wide), vec![vec![(1, 2), (2, 2), (3, 2)]]);
 
+    // Three columns of five rows, with a run up the last column.
+    let mut tall = Grid::new(3, 5);
+    for (index, coord) in tall.coords().collect::<Vec<_>>().into_iter().enumerate() {
+      tall.set(coord, Some(if index % 2 == 0 { TileType::Square } else { TileType::Diamond }));
+    }
+    for row in 2..5 {
+      tall.set((2, row), Some(TileType::Star));
+    }
+    assert_eq!(find_shapes(&tall), vec![vec![(2, 2), (2, 3), (2, 4)]]);
+  }
+  #[test]
+  fn apply_gravity_works_on_a_wide_board() {
+    let mut grid = Grid::from_columns(vec![
+      vec![None, Some(TileType::Star)],
+      vec![Some(TileType::Circle), None],
+      vec![None, None],
+      vec![None, Some(TileType::Square)],
+    ]);
+    let drops = apply_gravity(&mut grid);
+    assert_eq!(grid.row(0).map(|(_, tile)| tile).collect::<Vec<_>>(), vec![
+      Some(TileType::Star),
+      Some(TileType::Circle),
+      None,
+      Some(TileType::Square),
+    ]);
+    assert_eq!(drops.len(), 2);
+  }
+  #[test]
+  fn neighbour_stays_on_the_board() {
+    let grid = Grid::new(4, 2);
+    assert_eq!(grid.neighbour((0, 0), SwapDirection::Left), None);
+    assert_eq!(grid.neighbour((0, 0), SwapDirection::Down), None);
+    assert_eq!(grid.neighbour((3, 1), SwapDirection::Right), None);
+    assert_eq!(grid.neighbour((3, 1), SwapDirection::Up), None);
+    assert_eq!(grid.neighbour((3, 0), SwapDirection::Up), Some((3, 1)));
+    assert_eq!(grid.neighbours((1, 1)).count(), 3);
+  }
+  #[test]
+  fn out_of_bounds_access_is_ignored() {
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Circle)], vec![Some(TileType::Star)]]);
+    assert_eq!(grid.get((2, 0)), None);
+    grid.set((0, 5), Some(TileType::Square));
+    assert!(!grid.swap((0, 0), (0, 1)));
+    assert!(!can_swap(&mut grid, (1, 0), (2, 0)));
+    assert!(grid.swap((0, 0), (1, 0)));
+    assert_eq!(column(&grid, 0), vec![Some(TileType::Star)]);
+  }
+  #[test]
+  fn board_mask_from_rows_lists_the_top_row_first() {
+    let mask = BoardMask::from_rows(&[
+      ".#.",
+      "###",
+    ]);
+    assert_eq!((mask.width, mask.height), (3, 2));
+    assert!(mask.is_cell((1, 1)));
+    assert!(!mask.is_cell((0, 1)));
+    assert!(mask.is_cell((0, 0)));
+    assert!(!mask.is_cell((3, 0)));
+  }
+  #[test]
+  fn layouts_cut_holes_in_the_rectangle() {
+    assert_eq!(BoardLayout::Rectangle.mask(6, 6), BoardMask::full(6, 6));
+    assert_eq!(BoardLayout::Diamond.mask(5, 5), BoardMask::from_rows(&[
+      "..#..",
+      ".###.",
+      "#####",
+      ".###.",
+      "..#..",
+    ]));
+    let heart = BoardLayout::Heart.mask(6, 6);
+    assert!(!heart.is_cell((0, 0)));
+    assert!(heart.is_cell((2, 0)));
+    assert!(heart.is_cell((1, 5)));
+    assert!(!heart.is_cell((2, 5)));
+  }
+  #[test]
+  fn holes_never_hold_tiles() {
+    let mut grid = Grid::with_mask(BoardMask::from_rows(&[
+      "#.#",
+      "###",
+    ]));
+    grid.set((1, 1), Some(TileType::Circle));
+    assert_eq!(grid.get((1, 1)), None);
+    assert!(!grid.swap((1, 0), (1, 1)));
+    assert_eq!(grid.neighbour((1, 0), SwapDirection::Up), None);
+    assert_eq!(grid.coords().count(), 5);
+    let spawned = refill_grid(&mut grid, &TileSet::default(), &mut StdRng::seed_from_u64(1));
+    assert_eq!(spawned.len(), 5);
+    assert_eq!(grid.get((1, 1)), None);
+  }
+  #[test]
+  fn holes_break_up_runs() {
+    let mut grid = Grid::with_mask(BoardMask::from_rows(&[
+      "#",
+      "#",
+      ".",
+      "#",
+      "#",
+    ]));
+    for coord in grid.coords().collect::<Vec<_>>() {
+      grid.set(coord, Some(TileType::Circle));
+    }
+    assert!(find_shapes(&grid).is_empty());
+    assert!(!has_shape(&grid));
+    assert!(!has_possible_swaps(&mut grid));
+  }
+  #[test]
+  fn apply_gravity_drops_tiles_past_holes() {
+    let mut grid = Grid::with_mask(BoardMask::from_rows(&[
+      "#",
+      ".",
+      "#",
+      "#",
+    ]));
+    grid.set((0, 3), Some(TileType::Star));
+    grid.set((0, 0), Some(TileType::Circle));
+    let drops = apply_gravity(&mut grid);
+    assert_eq!(drops, vec![TileMove { from: (0, 3), to: (0, 1) }]);
+    assert_eq!(column(&grid, 0), vec![Some(TileType::Circle), Some(TileType::Star), None, None]);
+  }
+  #[test]
+  fn create_grid_fills_a_masked_board() {
+    let mask = BoardLayout::Diamond.mask(7, 7);
+    let mut grid = create_grid(&mask, &TileSet::default(), &mut StdRng::seed_from_u64(5));
+    for column in 0..7 {
+      for row in 0..7 {
+        assert_eq!(grid.get((column, row)).is_some(), mask.is_cell((column, row)));
+      }
+    }
+    assert!(find_shapes(&grid).is_empty());
+    assert!(has_possible_swaps(&mut grid));
+  }
+  #[test]
+  fn locked_tiles_cannot_be_swapped() {
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Star), Some(TileType::Circle)],
+      vec![Some(TileType::Star), Some(TileType::Circle), Some(TileType::Square)],
+    ]);
+    assert!(can_swap(&mut grid, (0, 1), (1, 1)));
+    grid.set_obstacles((1, 1), Obstacles { chain: true, ..Default::default() });
+    assert!(!grid.swap((0, 1), (1, 1)));
+    assert!(!can_swap(&mut grid, (0, 1), (1, 1)));
+    assert!(!has_possible_swaps(&mut grid));
+  }
+  #[test]
+  fn tiles_stop_on_a_stone_in_the_middle_of_a_column() {
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Circle), None, None, None, Some(TileType::Star)]]);
+    grid.set_obstacles((0, 2), Obstacles { stone: true, ..Default::default() });
+    let drops = apply_gravity(&mut grid);
+    assert_eq!(drops, vec![TileMove { from: (0, 4), to: (0, 3) }]);
+    assert_eq!(column(&grid, 0), vec![Some(TileType::Circle), None, None, Some(TileType::Star), None]);
+    // Only the cells above the stone are open to the top
+    let spawned = refill_grid(&mut grid, &TileSet::default(), &mut StdRng::seed_from_u64(1));
+    assert_eq!(spawned.iter().map(|(coord, _)| *coord).collect::<Vec<_>>(), vec![(0, 4)]);
+    assert_eq!(grid.get((0, 1)), None);
+    // Once the stone breaks the column falls and fills as one
+    grid.set_obstacles((0, 2), Obstacles::default());
+    assert_eq!(apply_gravity(&mut grid).len(), 2);
+    assert_eq!(refill_grid(&mut grid, &TileSet::default(), &mut StdRng::seed_from_u64(1)).len(), 2);
+  }
+  #[test]
+  fn chained_tiles_hold_up_the_tiles_above() {
+    let mut grid = Grid::from_columns(vec![vec![None, Some(TileType::Circle), None, Some(TileType::Star)]]);
+    grid.set_obstacles((0, 1), Obstacles { chain: true, ..Default::default() });
+    let drops = apply_gravity(&mut grid);
+    assert_eq!(drops, vec![TileMove { from: (0, 3), to: (0, 2) }]);
+    assert_eq!(column(&grid, 0), vec![None, Some(TileType::Circle), Some(TileType::Star), None]);
+  }
+  #[test]
+  fn stones_break_up_runs() {
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Circle), Some(TileType::Circle), None, Some(TileType::Circle)]]);
+    grid.set_obstacles((0, 2), Obstacles { stone: true, ..Default::default() });
+    assert!(find_shapes(&grid).is_empty());
+  }
+  #[test]
+  fn matches_break_the_obstacles_they_touch() {
+    // A run along the bottom row, with ice and a chain under it and a stone above it.
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Star), Some(TileType::Square)],
+      vec![Some(TileType::Circle), None, Some(TileType::Diamond)],
+      vec![Some(TileType::Circle), Some(TileType::Square), Some(TileType::Star)],
+    ]);
+    grid.set_obstacles((0, 0), Obstacles { ice: 2, ..Default::default() });
+    grid.set_obstacles((2, 0), Obstacles { chain: true, ..Default::default() });
+    grid.set_obstacles((1, 1), Obstacles { stone: true, ..Default::default() });
+    let step = resolve_step(&mut grid, &mut Specials::default(), &[], None, &TileSet::default(), &mut StdRng::seed_from_u64(1)).unwrap();
+    assert_eq!(step.broken, vec![((0, 0), Obstacle::Ice), ((2, 0), Obstacle::Chain), ((1, 1), Obstacle::Stone)]);
+    assert_eq!(grid.obstacles((0, 0)).ice, 1);
+    assert!(!grid.is_locked((2, 0)));
+    assert!(!grid.is_locked((1, 1)));
+    assert!(grid.coords().all(|coord| grid.get(coord).is_some()));
+  }
+  #[test]
+  fn ingredients_never_match_or_get_cleared() {
+    let mut grid = Grid::from_columns(vec![vec![Some(TileType::Ingredient); 3], vec![Some(TileType::Circle); 3]]);
+    assert_eq!(find_shapes(&grid), vec![vec![(1, 0), (1, 1), (1, 2)]]);
+    assert!(!has_shape(&Grid::from_columns(vec![vec![Some(TileType::Ingredient); 3]])));
+    let removed = remove_tiles(&mut grid, &[(0, 0), (1, 0)]);
+    assert_eq!(removed, vec![((1, 0), TileType::Circle)]);
+    assert_eq!(grid.get((0, 0)), Some(TileType::Ingredient));
+  }
+  #[test]
+  fn ingredients_are_collected_when_they_reach_the_bottom() {
+    // The run at the bottom of column 0 clears, and the ingredient above it falls all the way down.
+    // The one in column 1 only falls as far as the tile below it.
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Star), Some(TileType::Square), Some(TileType::Ingredient)],
+      vec![Some(TileType::Circle), Some(TileType::Diamond), Some(TileType::Ingredient), Some(TileType::Square)],
+      vec![Some(TileType::Circle), Some(TileType::Square), Some(TileType::Star), Some(TileType::Diamond)],
+    ]);
+    grid.set((0, 1), None);
+    grid.set((0, 2), None);
+    let mut rng = StdRng::seed_from_u64(1);
+    let step = resolve_step(&mut grid, &mut Specials::default(), &[], None, &TileSet::default(), &mut rng).unwrap();
+    assert!(step.collected.is_empty());
+    assert_eq!(tiles_of_type(&grid, TileType::Ingredient), vec![(0, 0), (1, 1)]);
+    // It is collected by the next step, once it has landed
+    let step = resolve_step(&mut grid, &mut Specials::default(), &[], None, &TileSet::default(), &mut rng).unwrap();
+    assert_eq!(step.collected, vec![(0, 0)]);
+    assert_eq!(tiles_of_type(&grid, TileType::Ingredient), vec![(1, 1)]);
+    assert!(grid.coords().all(|coord| grid.get(coord).is_some()));
+  }
+  #[test]
+  fn ingredients_are_not_collected_through_locked_cells() {
+    let mut grid = Grid::from_columns(vec![
+      vec![None, Some(TileType::Ingredient)],
+      vec![Some(TileType::Ingredient), Some(TileType::Circle)],
+    ]);
+    grid.set_obstacles((0, 0), Obstacles { stone: true, ..Default::default() });
+    assert_eq!(ingredients_at_bottom(&grid), vec![(1, 0)]);
+    grid.set_obstacles((1, 0), Obstacles { chain: true, ..Default::default() });
+    assert!(ingredients_at_bottom(&grid).is_empty());
+  }
 }
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+use super::{components::*, drag::*, grid::*, resolve::*, resources::*, score::*, shuffle::*, specials::*, state::*, system_sets::*, tile_set::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PossibleMove {
+  pub tile: Coord,
+  pub other: Coord,
+  pub direction: SwapDirection,
+}
+
+// Every swap the player could make right now.
+// Each pair of tiles is only listed once, as a move to the right or up.
+pub fn find_possible_moves(grid: &mut Grid, specials: &Specials) -> Vec<PossibleMove> {
+  let mut moves = vec![];
+  for tile in grid.coords().collect::<Vec<_>>() {
+    for direction in [SwapDirection::Right, SwapDirection::Up] {
+      if let Some(other) = grid.neighbour(tile, direction) {
+        let locked = grid.is_locked(tile) || grid.is_locked(other);
+        if (!locked && activates_on_swap(specials, tile, other)) || can_swap(grid, tile, other) {
+          moves.push(PossibleMove { tile, other, direction });
+        }
+      }
+    }
+  }
+  moves
+}
+
+// The points the first step of the move would score, before any cascades.
+pub fn expected_score(grid: &Grid, specials: &Specials, possible_move: &PossibleMove) -> u32 {
+  let mut grid = grid.clone();
+  let mut specials = specials.clone();
+  grid.swap(possible_move.tile, possible_move.other);
+  swap_specials(&mut specials, possible_move.tile, possible_move.other);
+  let activation = activate_swap(&grid, &mut specials, possible_move.tile, possible_move.other);
+  // Only the first step is scored, so the refill doesn't matter.
+  // It gets its own generator so that hints don't change the game's random sequence.
+  let mut rng = StdRng::seed_from_u64(0);
+  resolve_step(&mut grid, &mut specials, &[possible_move.tile, possible_move.other], activation, &TileSet::default(), &mut rng)
+    .map(|step| score_step(&step, 1).total)
+    .unwrap_or(0)
+}
+
+pub fn best_move(grid: &mut Grid, specials: &Specials) -> Option<PossibleMove> {
+  let moves = find_possible_moves(grid, specials);
+  let mut best: Option<(u32, PossibleMove)> = None;
+  for possible_move in moves {
+    let score = expected_score(grid, specials, &possible_move);
+    if best.is_none_or(|(best_score, _)| score > best_score) {
+      best = Some((score, possible_move));
+    }
+  }
+  best.map(|(_, possible_move)| possible_move)
+}
+
+// A board the player could be making a move on
+type IdleBoard = (Without<TileIsDragging>, Without<ResolveBoard>, Without<ShuffleBoard>);
+
+pub fn show_hint(
+  mut commands: Commands,
+  time: Res<Time>,
+  mut hint_timer: ResMut<HintTimer>,
+  game_data: Query<&GameData, IdleBoard>,
+  hint_tiles: Query<Entity, With<HintTile>>,
+  tiles: Query<(Entity, &Tile)>,
+) {
+  if !hint_tiles.is_empty() || !hint_timer.timer.tick(time.delta()).finished() {
+    return;
+  }
+  for game_data in game_data.iter() {
+    let mut grid = game_data.grid.clone();
+    if let Some(possible_move) = best_move(&mut grid, &game_data.specials) {
+      for (entity, tile) in tiles.iter() {
+        let coord = (tile.column, tile.row);
+        if coord == possible_move.tile || coord == possible_move.other {
+          commands.entity(entity).insert(HintTile);
+        }
+      }
+    }
+  }
+}
+
+pub fn pulse_hint_tiles(
+  time: Res<Time>,
+  mut hint_tiles: Query<&mut Sprite, With<HintTile>>,
+) {
+  let alpha = 0.65 + 0.35 * (time.elapsed_seconds() * 6.0).cos();
+  for mut sprite in hint_tiles.iter_mut() {
+    sprite.color.set_a(alpha);
+  }
+}
+
+// Any drag, resolve or shuffle means the player is no longer idle, and the hinted tiles may have moved.
+pub fn clear_hint(
+  mut commands: Commands,
+  mut hint_timer: ResMut<HintTimer>,
+  mut tile_drag_start_evr: EventReader<TileDragStart>,
+  mut board_resolved_evr: EventReader<BoardResolved>,
+  mut board_shuffled_evr: EventReader<BoardShuffled>,
+  mut hint_tiles: Query<(Entity, &mut Sprite), With<HintTile>>,
+) {
+  let drag_started = tile_drag_start_evr.iter().count() > 0;
+  let board_resolved = board_resolved_evr.iter().count() > 0;
+  let board_shuffled = board_shuffled_evr.iter().count() > 0;
+  if !(drag_started || board_resolved || board_shuffled) {
+    return;
+  }
+  hint_timer.timer.reset();
+  for (entity, mut sprite) in hint_tiles.iter_mut() {
+    sprite.color.set_a(1.0);
+    commands.entity(entity).remove::<HintTile>();
+  }
+}
+
+pub fn setup_hint_timer(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+) {
+  commands.insert_resource(HintTimer {
+    timer: Timer::from_seconds(game_config.hint_seconds, TimerMode::Once),
+  });
+}
+
+pub fn add_hint_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<HintTile>()
+    .add_system(setup_hint_timer.in_schedule(OnExit(GameState::MainMenu)))
+    .add_systems((
+      clear_hint.run_if(resource_exists::<HintTimer>()),
+      show_hint.run_if(in_state(GameState::Playing)),
+      pulse_hint_tiles,
+    ).chain().after(Shuffle))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn find_possible_moves_lists_each_swap_once() {
+    // Column 0 from the bottom: Circle Circle Star, and a Circle at (1, 2) to swap in.
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Circle), Some(TileType::Star)],
+      vec![Some(TileType::Square), Some(TileType::Diamond), Some(TileType::Circle)],
+      vec![Some(TileType::Diamond), Some(TileType::Square), Some(TileType::Pentagon)],
+    ]);
+    let moves = find_possible_moves(&mut grid, &Specials::default());
+    assert_eq!(moves, vec![PossibleMove { tile: (0, 2), other: (1, 2), direction: SwapDirection::Right }]);
+  }
+  #[test]
+  fn best_move_prefers_the_bigger_shape() {
+    // Swapping (1, 2) into column 0 makes a line of four,
+    // the other two moves only make lines of three.
+    let mut grid = Grid::from_columns(vec![
+      vec![Some(TileType::Circle), Some(TileType::Circle), Some(TileType::Star), Some(TileType::Circle)],
+      vec![Some(TileType::Square), Some(TileType::Diamond), Some(TileType::Circle), Some(TileType::Square)],
+      vec![Some(TileType::Pentagon), Some(TileType::Pentagon), Some(TileType::Triangle), Some(TileType::Pentagon)],
+    ]);
+    let specials = Specials::default();
+    assert_eq!(find_possible_moves(&mut grid, &specials).len(), 3);
+    let possible_move = best_move(&mut grid, &specials).unwrap();
+    assert_eq!((possible_move.tile, possible_move.other), ((0, 2), (1, 2)));
+    assert_eq!(expected_score(&grid, &specials, &possible_move), LINE_4_POINTS);
+  }
+}
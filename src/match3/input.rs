@@ -1,13 +1,11 @@
-use bevy::{prelude::*, input::{mouse::{MouseButtonInput, MouseMotion}, ButtonState}, window::PrimaryWindow};
+use bevy::{prelude::*, ecs::system::SystemParam, input::{mouse::{MouseButtonInput, MouseMotion}, touch::{TouchInput, TouchPhase}, ButtonState}, window::{CursorLeft, PrimaryWindow, WindowFocused}};
 
-use super::{drag::*, resources::*};
-
-#[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
-pub struct MouseInput;
+use super::{coords::*, drag::*, resources::*, state::*, system_sets::*};
 
 pub fn handle_mousebtn(
   mut mouse_btn_evr: EventReader<MouseButtonInput>,
   windows: Query<&Window, With<PrimaryWindow>>,
+  board_pointer: BoardPointer,
   mut tile_drag_start_evw: EventWriter<TileDragStart>,
   mut tile_drag_end_evw: EventWriter<TileDragEnd>
 ) {
@@ -15,13 +13,13 @@ pub fn handle_mousebtn(
     match (evt.button, evt.state) {
       (MouseButton::Left, ButtonState::Pressed) => {
         for window in windows.iter() {
-          if let Some(position) = window.cursor_position() {
-            tile_drag_start_evw.send(TileDragStart {
-              start_coord: position.into(),
-              column: (position.x / 80.) as usize,
-              row: (position.y / 80.) as usize,
-            });
-          }
+          let Some(position) = window.cursor_position() else {
+            continue;
+          };
+          let (Some(start_coord), Some((column, row))) = (board_pointer.screen_to_local(position), board_pointer.screen_to_cell(position)) else {
+            continue;
+          };
+          tile_drag_start_evw.send(TileDragStart { start_coord, column, row });
         }
       }
       (MouseButton::Left, ButtonState::Released) => {
@@ -32,19 +30,109 @@ pub fn handle_mousebtn(
   }
 }
 
+// Mouse motion is in screen pixels, so it is measured on the board to follow the camera's zoom.
 pub fn handle_mousemove(
   game_data: Query<&GameData, With<TileIsDragging>>,
+  windows: Query<&Window, With<PrimaryWindow>>,
+  board_pointer: BoardPointer,
   mut mouse_moves_evr: EventReader<MouseMotion>,
   mut tile_drag_move_evw: EventWriter<TileDragMove>
 ) {
   if game_data.is_empty() {
     return;
   }
+  let Some(position) = windows.get_single().ok().and_then(|window| window.cursor_position()) else {
+    return;
+  };
 
   for event in mouse_moves_evr.iter() {
-    tile_drag_move_evw.send(TileDragMove {
-      delta_coord: event.delta * Vec2 { x: 1., y: -1. },
-    });
+    let previous = position - event.delta * Vec2 { x: 1., y: -1. };
+    if let (Some(now), Some(before)) = (board_pointer.screen_to_local(position), board_pointer.screen_to_local(previous)) {
+      tile_drag_move_evw.send(TileDragMove {
+        delta_coord: now - before,
+      });
+    }
+  }
+}
+
+#[derive(SystemParam)]
+pub struct DragEventWriters<'w> {
+  start: EventWriter<'w, TileDragStart>,
+  moved: EventWriter<'w, TileDragMove>,
+  end: EventWriter<'w, TileDragEnd>,
+  cancel: EventWriter<'w, TileDragCancel>,
+}
+
+// Touches are reported from the top left of the window, the cursor from the bottom left.
+pub fn handle_touch(
+  mut touch_evr: EventReader<TouchInput>,
+  mut active_touch: ResMut<ActiveTouch>,
+  board_pointer: BoardPointer,
+  windows: Query<&Window, With<PrimaryWindow>>,
+  mut drag_evw: DragEventWriters,
+) {
+  let Ok(window) = windows.get_single() else {
+    return;
+  };
+  for evt in touch_evr.iter() {
+    let position = Vec2::new(evt.position.x, window.height() - evt.position.y);
+    match evt.phase {
+      TouchPhase::Started => {
+        if active_touch.id.is_some() {
+          continue;
+        }
+        let (Some(start_coord), Some((column, row))) = (board_pointer.screen_to_local(position), board_pointer.screen_to_cell(position)) else {
+          continue;
+        };
+        active_touch.id = Some(evt.id);
+        active_touch.last_position = start_coord;
+        drag_evw.start.send(TileDragStart { start_coord, column, row });
+      }
+      _ if active_touch.id != Some(evt.id) => {}
+      TouchPhase::Moved => {
+        if let Some(position) = board_pointer.screen_to_local(position) {
+          drag_evw.moved.send(TileDragMove {
+            delta_coord: position - active_touch.last_position,
+          });
+          active_touch.last_position = position;
+        }
+      }
+      TouchPhase::Ended => {
+        active_touch.id = None;
+        drag_evw.end.send(TileDragEnd {});
+      }
+      TouchPhase::Cancelled => {
+        active_touch.id = None;
+        drag_evw.cancel.send(TileDragCancel {});
+      }
+    }
+  }
+}
+
+// Touch events aren't read outside of Playing, so a finger lifted in the meantime would never be released.
+pub fn reset_active_touch(
+  mut active_touch: ResMut<ActiveTouch>,
+) {
+  active_touch.id = None;
+}
+
+// A drag is dropped if the cursor leaves the window, the window loses focus,
+// or the player presses the right mouse button or Escape.
+pub fn handle_drag_cancel(
+  game_data: Query<&GameData, With<TileIsDragging>>,
+  keys: Res<Input<KeyCode>>,
+  mouse_buttons: Res<Input<MouseButton>>,
+  mut cursor_left_evr: EventReader<CursorLeft>,
+  mut window_focused_evr: EventReader<WindowFocused>,
+  mut tile_drag_cancel_evw: EventWriter<TileDragCancel>,
+) {
+  let cursor_left = cursor_left_evr.iter().count() > 0;
+  let focus_lost = window_focused_evr.iter().any(|evt| !evt.focused);
+  if game_data.is_empty() {
+    return;
+  }
+  if cursor_left || focus_lost || keys.just_pressed(KeyCode::Escape) || mouse_buttons.just_pressed(MouseButton::Right) {
+    tile_drag_cancel_evw.send(TileDragCancel {});
   }
 }
 
@@ -52,6 +140,7 @@ pub fn add_input_to_app(
   app: &mut App
 ) -> &mut App {
   app
-    .configure_set(MouseInput.before(GameEvents))
-    .add_systems((handle_mousebtn, handle_mousemove).in_set(MouseInput))
+    .init_resource::<ActiveTouch>()
+    .add_systems((handle_mousebtn, handle_mousemove, handle_touch, handle_drag_cancel).in_set(MouseInput))
+    .add_system(reset_active_touch.in_schedule(OnExit(GameState::Playing)))
 }
\ No newline at end of file
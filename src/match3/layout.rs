@@ -0,0 +1,55 @@
+use bevy::{prelude::*, window::{PrimaryWindow, WindowResized}};
+
+use super::resources::*;
+
+// Space left around the board, in cells
+const MARGIN: f32 = 0.5;
+
+// How far the camera has to zoom out for the board and its margin to fit in the window.
+// Below 1 it zooms in. None while the window has no size, e.g. when it is minimised.
+pub fn camera_scale(window: Vec2, board: Vec2, margin: f32) -> Option<f32> {
+  if window.x <= 0.0 || window.y <= 0.0 {
+    return None;
+  }
+  Some(((board + Vec2::splat(margin * 2.0)) / window).max_element())
+}
+
+// The board stays at the origin and keeps its cell size,
+// the camera moves and zooms so it sits in the middle of the window whatever its size.
+// Tiles never move, so a drag in progress carries on as if nothing happened.
+pub fn fit_camera_to_board(
+  game_config: Res<GameConfig>,
+  windows: Query<&Window, With<PrimaryWindow>>,
+  mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+  let Ok(window) = windows.get_single() else {
+    return;
+  };
+  let board = Vec2::new(game_config.columns as f32, game_config.rows as f32) * game_config.cell_size;
+  let Some(scale) = camera_scale(Vec2::new(window.width(), window.height()), board, MARGIN * game_config.cell_size) else {
+    return;
+  };
+  for (mut transform, mut projection) in cameras.iter_mut() {
+    transform.translation.x = board.x / 2.0;
+    transform.translation.y = board.y / 2.0;
+    projection.scale = scale;
+  }
+}
+
+pub fn add_layout_to_app(app: &mut App) -> &mut App {
+  app
+    .add_system(fit_camera_to_board.run_if(on_event::<WindowResized>().or_else(resource_changed::<GameConfig>())))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn camera_scale_fits_the_tighter_side() {
+    let board = Vec2::new(480.0, 480.0);
+    assert_eq!(camera_scale(Vec2::new(560.0, 560.0), board, 40.0), Some(1.0));
+    assert_eq!(camera_scale(Vec2::new(1120.0, 280.0), board, 40.0), Some(2.0));
+    assert_eq!(camera_scale(Vec2::new(1120.0, 1120.0), board, 40.0), Some(0.5));
+    assert_eq!(camera_scale(Vec2::new(0.0, 480.0), board, 40.0), None);
+  }
+}
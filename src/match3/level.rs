@@ -0,0 +1,423 @@
+use std::{collections::HashSet, fmt};
+
+use bevy::{prelude::*, asset::{AssetLoader, LoadContext, LoadState, LoadedAsset}, reflect::TypeUuid, utils::BoxedFuture};
+use serde::Deserialize;
+
+use super::{grid::*, resources::*, state::*, tile_set::*};
+
+// What the player has to do before running out of moves or time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Objective {
+  Score(u32),
+  // Clear this many tiles of one kind
+  Clear(TileType, u32),
+  // Bring this many ingredients down to the bottom of the board. They are placed with 'I' on the board.
+  Collect(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Limit {
+  Moves(u32),
+  Seconds(f32),
+}
+
+// A level, loaded from a `.level.ron` file in the assets folder:
+//
+// Level(
+//   name: "Example",
+//   board: [
+//     "######",
+//     ".#CC#.",
+//     "..##..",
+//     ".####.",
+//     "######",
+//   ],
+//   obstacles: [
+//     "X....X",
+//     "......",
+//     "..LL..",
+//     "......",
+//     "122221",
+//   ],
+//   colors: Some(5),
+//   limit: Some(Moves(20)),
+//   objectives: [Score(2000), Clear(Circle, 15)],
+// )
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "7f3b6e2a-4c1d-4e8f-9a5b-2d6c8e1f0a93"]
+pub struct Level {
+  pub name: String,
+  // The board from the top row down, one character per cell.
+  // '.' is a hole, '#' is a cell that starts with a random tile, and the symbols in tile_for_symbol start with that tile.
+  pub board: Vec<String>,
+  // An optional second layer the same size as the board, for what is in each cell besides its tile.
+  // '1' to '9' are layers of ice, 'L' chains the tile and 'X' is a stone instead of a tile. Anything else is nothing.
+  #[serde(default)]
+  pub obstacles: Vec<String>,
+  // Use the first few colours of the standard tile set
+  #[serde(default)]
+  pub colors: Option<usize>,
+  // A tile set of its own, used instead of colors
+  #[serde(default)]
+  pub tile_set: Option<TileSet>,
+  #[serde(default)]
+  pub limit: Option<Limit>,
+  #[serde(default)]
+  pub objectives: Vec<Objective>,
+  // The board with its starting tiles, built from board when the level is loaded
+  #[serde(skip)]
+  pub template: Grid,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+  Parse(ron::error::SpannedError),
+  EmptyBoard,
+  UnevenRows,
+  UnknownSymbol(char),
+  ObstaclesDontMatchBoard,
+  BoardStartsWithShape,
+  ColorsOutOfRange(usize),
+  BadWeight(TileType),
+  TooFewColors(usize),
+  IngredientInTileSet,
+  TileNotInTileSet(TileType),
+}
+
+impl fmt::Display for LevelError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LevelError::Parse(error) => write!(f, "the level isn't valid RON: {}", error),
+      LevelError::EmptyBoard => write!(f, "the level's board has no cells"),
+      LevelError::UnevenRows => write!(f, "the rows of the level's board are not all the same length"),
+      LevelError::UnknownSymbol(symbol) => write!(f, "'{}' on the level's board is not a tile", symbol),
+      LevelError::ObstaclesDontMatchBoard => write!(f, "the level's obstacles are not the same size as its board"),
+      LevelError::BoardStartsWithShape => write!(f, "the tiles on the level's board already make a shape"),
+      LevelError::ColorsOutOfRange(colors) => write!(
+        f,
+        "the level's colors must be between {} and {}, not {}",
+        TileSet::MIN_COLORS,
+        TileSet::all().kinds.len(),
+        colors
+      ),
+      LevelError::BadWeight(tile_type) => write!(f, "the weight of {:?} in the level's tile set must be a number no less than zero", tile_type),
+      LevelError::TooFewColors(colors) => write!(
+        f,
+        "the level's tile set needs {} kinds with a weight above zero, it has {}",
+        TileSet::MIN_COLORS,
+        colors
+      ),
+      LevelError::IngredientInTileSet => write!(f, "ingredients are placed on the level's board, they can't be in its tile set"),
+      LevelError::TileNotInTileSet(tile_type) => write!(f, "{:?} on the level's board is not in its tile set", tile_type),
+    }
+  }
+}
+
+impl std::error::Error for LevelError {}
+
+pub fn tile_for_symbol(symbol: char) -> Option<TileType> {
+  match symbol {
+    'P' => Some(TileType::Pentagon),
+    'T' => Some(TileType::Triangle),
+    'S' => Some(TileType::Square),
+    'C' => Some(TileType::Circle),
+    'D' => Some(TileType::Diamond),
+    '*' => Some(TileType::Star),
+    'E' => Some(TileType::Emerald),
+    'I' => Some(TileType::Ingredient),
+    _ => None,
+  }
+}
+
+// Turn the rows of a level's board into a grid holding just its fixed starting tiles.
+pub fn parse_board(board: &[String]) -> Result<Grid, LevelError> {
+  let width = board.first().map_or(0, |row| row.chars().count());
+  if width == 0 {
+    return Err(LevelError::EmptyBoard);
+  }
+  if board.iter().any(|row| row.chars().count() != width) {
+    return Err(LevelError::UnevenRows);
+  }
+  let mask_rows = board
+    .iter()
+    .map(|row| row.chars().map(|symbol| if symbol == '.' { '.' } else { '#' }).collect::<String>())
+    .collect::<Vec<_>>();
+  let mut grid = Grid::with_mask(BoardMask::from_rows(&mask_rows.iter().map(String::as_str).collect::<Vec<_>>()));
+  for (index, line) in board.iter().enumerate() {
+    let row = board.len() - 1 - index;
+    for (column, symbol) in line.chars().enumerate() {
+      match symbol {
+        '.' | '#' => {}
+        _ => grid.set((column, row), Some(tile_for_symbol(symbol).ok_or(LevelError::UnknownSymbol(symbol))?)),
+      }
+    }
+  }
+  Ok(grid)
+}
+
+pub fn obstacles_for_symbol(symbol: char) -> Obstacles {
+  match symbol {
+    'L' => Obstacles { chain: true, ..Default::default() },
+    'X' => Obstacles { stone: true, ..Default::default() },
+    _ => Obstacles { ice: symbol.to_digit(10).unwrap_or(0) as u8, ..Default::default() },
+  }
+}
+
+// Lay the obstacles over a grid parsed from the same level. Stones take the place of any tile in their cell.
+pub fn parse_obstacles(grid: &mut Grid, obstacles: &[String]) -> Result<(), LevelError> {
+  if obstacles.len() != grid.height() || obstacles.iter().any(|row| row.chars().count() != grid.width()) {
+    return Err(LevelError::ObstaclesDontMatchBoard);
+  }
+  for (index, line) in obstacles.iter().enumerate() {
+    let row = obstacles.len() - 1 - index;
+    for (column, symbol) in line.chars().enumerate() {
+      let cell_obstacles = obstacles_for_symbol(symbol);
+      if cell_obstacles.stone {
+        grid.set((column, row), None);
+      }
+      grid.set_obstacles((column, row), cell_obstacles);
+    }
+  }
+  Ok(())
+}
+
+impl Level {
+  pub fn from_bytes(bytes: &[u8]) -> Result<Level, LevelError> {
+    let mut level = ron::de::from_bytes::<Level>(bytes).map_err(LevelError::Parse)?;
+    level.template = parse_board(&level.board)?;
+    if !level.obstacles.is_empty() {
+      parse_obstacles(&mut level.template, &level.obstacles)?;
+    }
+    // Nothing resolves the board before the first move
+    if !find_shapes(&level.template).is_empty() {
+      return Err(LevelError::BoardStartsWithShape);
+    }
+    level.check_tile_set()?;
+    Ok(level)
+  }
+
+  // New tiles are picked from the tile set by weight, so it needs enough kinds that can be picked.
+  fn check_tile_set(&self) -> Result<(), LevelError> {
+    if let Some(colors) = self.colors {
+      if !(TileSet::MIN_COLORS..=TileSet::all().kinds.len()).contains(&colors) {
+        return Err(LevelError::ColorsOutOfRange(colors));
+      }
+    }
+    if let Some(tile_set) = &self.tile_set {
+      if let Some(kind) = tile_set.kinds.iter().find(|kind| !(kind.weight.is_finite() && kind.weight >= 0.0)) {
+        return Err(LevelError::BadWeight(kind.tile_type));
+      }
+      let colors = tile_set.kinds.iter().filter(|kind| kind.weight > 0.0).map(|kind| kind.tile_type).collect::<HashSet<_>>();
+      if colors.len() < TileSet::MIN_COLORS {
+        return Err(LevelError::TooFewColors(colors.len()));
+      }
+      if tile_set.kinds.iter().any(|kind| kind.tile_type == TileType::Ingredient) {
+        return Err(LevelError::IngredientInTileSet);
+      }
+    }
+    // Only the tile set's kinds have textures, besides ingredients
+    let tile_set = self.tile_set();
+    let fixed_tiles = self.template.coords().filter_map(|coord| self.template.get(coord));
+    for tile_type in fixed_tiles.filter(|&tile_type| tile_type != TileType::Ingredient) {
+      if !tile_set.kinds.iter().any(|kind| kind.tile_type == tile_type) {
+        return Err(LevelError::TileNotInTileSet(tile_type));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn tile_set(&self) -> TileSet {
+    match (&self.tile_set, self.colors) {
+      (Some(tile_set), _) => tile_set.clone(),
+      (None, Some(colors)) => TileSet::with_colors(colors),
+      (None, None) => TileSet::default(),
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+  fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+    Box::pin(async move {
+      let level = Level::from_bytes(bytes)?;
+      load_context.set_default_asset(LoadedAsset::new(level));
+      Ok(())
+    })
+  }
+
+  fn extensions(&self) -> &[&str] {
+    &["level.ron"]
+  }
+}
+
+// The level to play. Without one the board is random, shaped by the GameConfig.
+#[derive(Resource, Debug, Clone)]
+pub struct SelectedLevel(pub Handle<Level>);
+
+// Run condition that holds the game in the menu until the selected level has loaded.
+// The path of a level that couldn't be loaded, because its file is missing or isn't a valid level.
+// The game is played on a random board instead.
+#[derive(Resource, Debug, Clone)]
+pub struct FailedLevel(pub String);
+
+// Without this a level that fails to load would keep the game waiting in the menu for good.
+pub fn check_level_load(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+  selected_level: Option<Res<SelectedLevel>>,
+) {
+  let Some(selected_level) = selected_level else {
+    return;
+  };
+  if asset_server.get_load_state(&selected_level.0) != LoadState::Failed {
+    return;
+  }
+  let path = asset_server
+    .get_handle_path(&selected_level.0)
+    .map_or_else(|| "the level".to_string(), |path| path.path().display().to_string());
+  error!("Could not load {}, playing on a random board instead", path);
+  commands.remove_resource::<SelectedLevel>();
+  commands.insert_resource(FailedLevel(path));
+}
+
+pub fn level_loaded(
+  selected_level: Option<Res<SelectedLevel>>,
+  levels: Res<Assets<Level>>,
+) -> bool {
+  selected_level.is_none_or(|selected_level| levels.contains(&selected_level.0))
+}
+
+// The board's size and tiles come from the level, so the rest of the game sees them through GameConfig and TileSet.
+// This is done in the menu so that a level that is edited while the game runs is picked up for the next game.
+pub fn apply_level(
+  selected_level: Option<Res<SelectedLevel>>,
+  levels: Res<Assets<Level>>,
+  mut game_config: ResMut<GameConfig>,
+  mut tile_set: ResMut<TileSet>,
+) {
+  let Some(level) = selected_level.and_then(|selected_level| levels.get(&selected_level.0)) else {
+    return;
+  };
+  let (columns, rows) = (level.template.width(), level.template.height());
+  let level_tile_set = level.tile_set();
+  if game_config.columns == columns && game_config.rows == rows && *tile_set == level_tile_set {
+    return;
+  }
+  game_config.columns = columns;
+  game_config.rows = rows;
+  *tile_set = level_tile_set;
+}
+
+pub fn add_level_to_app(app: &mut App) -> &mut App {
+  app
+    .add_asset::<Level>()
+    .init_asset_loader::<LevelLoader>()
+    .add_system(check_level_load.run_if(in_state(GameState::MainMenu)))
+    .add_system(apply_level.run_if(in_state(GameState::MainMenu)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::match3::specials::tiles_of_type;
+  #[test]
+  fn level_loads_from_ron() {
+    let level = Level::from_bytes(br##"
+      Level(
+        name: "Test",
+        board: [
+          "#C.",
+          "T##",
+        ],
+        colors: Some(4),
+        limit: Some(Moves(20)),
+        objectives: [Score(500), Clear(Circle, 10)],
+      )
+    "##).unwrap();
+    assert_eq!((level.template.width(), level.template.height()), (3, 2));
+    assert!(!level.template.is_cell((2, 1)));
+    assert_eq!(level.template.get((1, 1)), Some(TileType::Circle));
+    assert_eq!(level.template.get((0, 0)), Some(TileType::Triangle));
+    assert_eq!(level.template.get((0, 1)), None);
+    assert_eq!(level.tile_set(), TileSet::with_colors(4));
+    assert_eq!(level.limit, Some(Limit::Moves(20)));
+    assert_eq!(level.objectives, vec![Objective::Score(500), Objective::Clear(TileType::Circle, 10)]);
+  }
+  #[test]
+  fn bundled_levels_load() {
+    let level = Level::from_bytes(include_bytes!("../../assets/levels/hourglass.level.ron")).unwrap();
+    assert_eq!((level.template.width(), level.template.height()), (7, 8));
+    let level = Level::from_bytes(include_bytes!("../../assets/levels/frozen.level.ron")).unwrap();
+    assert_eq!(level.template.obstacles((3, 1)).ice, 3);
+    let level = Level::from_bytes(include_bytes!("../../assets/levels/harvest.level.ron")).unwrap();
+    assert_eq!(tiles_of_type(&level.template, TileType::Ingredient).len(), 3);
+    assert_eq!(level.objectives[0], Objective::Collect(3));
+  }
+  #[test]
+  fn bad_boards_are_rejected() {
+    let board = |rows: &[&str]| parse_board(&rows.iter().map(|row| row.to_string()).collect::<Vec<_>>());
+    assert!(matches!(board(&[]), Err(LevelError::EmptyBoard)));
+    assert!(matches!(board(&["###", "##"]), Err(LevelError::UnevenRows)));
+    assert!(matches!(board(&["#X#"]), Err(LevelError::UnknownSymbol('X'))));
+    let mut grid = board(&["###", "###"]).unwrap();
+    assert!(matches!(parse_obstacles(&mut grid, &["..".to_string()]), Err(LevelError::ObstaclesDontMatchBoard)));
+    assert!(matches!(
+      Level::from_bytes(br#"Level(name: "Test", board: ["CCC", "T#T"])"#),
+      Err(LevelError::BoardStartsWithShape)
+    ));
+    let level = |tiles: &str| Level::from_bytes(format!(r#"Level(name: "Test", board: ["C#T"], {})"#, tiles).as_bytes());
+    assert!(matches!(level("colors: Some(0)"), Err(LevelError::ColorsOutOfRange(0))));
+    assert!(matches!(level("colors: Some(1)"), Err(LevelError::ColorsOutOfRange(1))));
+    assert!(matches!(level("colors: Some(2)"), Err(LevelError::ColorsOutOfRange(2))));
+    assert!(matches!(level("colors: Some(9)"), Err(LevelError::ColorsOutOfRange(9))));
+    let tile_set = |kinds: &[(&str, f32)]| {
+      let kinds = kinds
+        .iter()
+        .map(|(tile_type, weight)| format!(r#"(tile_type: {}, texture: "circle.png", weight: {:?})"#, tile_type, weight))
+        .collect::<Vec<_>>();
+      level(&format!("tile_set: Some((kinds: [{}]))", kinds.join(", ")))
+    };
+    assert!(matches!(tile_set(&[]), Err(LevelError::TooFewColors(0))));
+    assert!(matches!(tile_set(&[("Circle", 1.0), ("Triangle", 1.0), ("Star", 0.0)]), Err(LevelError::TooFewColors(2))));
+    assert!(matches!(tile_set(&[("Circle", 1.0), ("Circle", 2.0), ("Triangle", 1.0)]), Err(LevelError::TooFewColors(2))));
+    assert!(matches!(tile_set(&[("Circle", -1.0), ("Triangle", 1.0), ("Star", 1.0)]), Err(LevelError::BadWeight(TileType::Circle))));
+    assert!(matches!(
+      tile_set(&[("Circle", 1.0), ("Triangle", 1.0), ("Star", 1.0), ("Ingredient", 1.0)]),
+      Err(LevelError::IngredientInTileSet)
+    ));
+    assert!(matches!(
+      tile_set(&[("Circle", 1.0), ("Square", 1.0), ("Star", 1.0)]),
+      Err(LevelError::TileNotInTileSet(TileType::Triangle))
+    ));
+    assert!(tile_set(&[("Circle", 1.0), ("Triangle", 1.0), ("Star", 0.5)]).is_ok());
+    assert!(matches!(
+      Level::from_bytes(br#"Level(name: "Test", board: ["E#C"], colors: Some(5))"#),
+      Err(LevelError::TileNotInTileSet(TileType::Emerald))
+    ));
+    assert!(level("colors: Some(7)").is_ok());
+  }
+  #[test]
+  fn obstacles_are_laid_over_the_board() {
+    let level = Level::from_bytes(br##"
+      Level(
+        name: "Test",
+        board: [
+          "C#T",
+          "#S#",
+        ],
+        obstacles: [
+          "X.L",
+          "2..",
+        ],
+      )
+    "##).unwrap();
+    assert_eq!(level.template.get((0, 1)), None);
+    assert!(level.template.obstacles((0, 1)).stone);
+    assert!(level.template.obstacles((2, 1)).chain);
+    assert_eq!(level.template.obstacles((0, 0)).ice, 2);
+    assert_eq!(level.template.obstacles((1, 0)), Obstacles::default());
+    assert!(!level.template.is_empty((0, 1)));
+  }
+}
@@ -8,6 +8,24 @@ mod bundles;
 mod system_sets;
 mod setup;
 mod drag;
+mod resolve;
+mod score;
+mod shapes;
+mod specials;
+mod shuffle;
+mod hint;
+mod state;
+mod tween;
+mod effects;
+mod cursor;
+mod coords;
+mod layout;
+mod tile_set;
+mod level;
+mod objectives;
+mod countdown;
+mod obstacles;
+mod ui;
 use self::grid::*;
 use self::resources::*;
 use self::components::*;
@@ -15,23 +33,253 @@ use self::setup::*;
 use self::input::*;
 
 use self::drag::*;
+use self::resolve::*;
+use self::score::*;
+use self::shuffle::*;
+use self::hint::*;
+use self::state::*;
+use self::tween::*;
+use self::effects::*;
+use self::cursor::*;
+use self::layout::*;
+use self::tile_set::*;
+use self::level::*;
+use self::objectives::*;
+use self::countdown::*;
+use self::obstacles::*;
+use self::ui::*;
 
-pub struct Match3Plugin;
+pub use self::resources::GameRng;
+pub use self::grid::BoardLayout;
+pub use self::tile_set::TileSet;
 
-impl Plugin for Match3Plugin {
-  fn build(&self, app: &mut App) {
-    app
-      .init_resource::<GameConfig>()
-      .insert_resource(GameConfig {
+// Adds the game to an app. The board and the parts of the game that are included can be set up with the builder methods:
+// `Match3Plugin::new().rows(8).columns(8).tile_set(TileSet::with_colors(5)).seed(42).cursor(false)`
+// or played on a level from the assets folder: `Match3Plugin::new().level("levels/hourglass.level.ron")`
+// A GameConfig or TileSet resource that is already in the app is used instead of the one built here.
+#[derive(Debug, Clone)]
+pub struct Match3Plugin {
+  config: GameConfig,
+  tile_set: TileSet,
+  // Path of the level to play, which sets the size of the board and the tile set
+  level: Option<String>,
+  // Swapping by dragging tiles with the mouse or a finger
+  drag: bool,
+  // Swapping with the keyboard or a gamepad
+  cursor: bool,
+  hints: bool,
+  // Animations and particles when tiles are cleared
+  effects: bool,
+  // The score, the menu and the messages shown when the game is paused or over
+  ui: bool,
+}
+
+impl Default for Match3Plugin {
+  fn default() -> Self {
+    Match3Plugin {
+      config: GameConfig {
         rows: 6,
         columns: 6,
+        layout: BoardLayout::Rectangle,
         cell_size: 80.,
-      })
+        hint_seconds: 5.,
+        seed: None,
+      },
+      tile_set: TileSet::default(),
+      level: None,
+      drag: true,
+      cursor: true,
+      hints: true,
+      effects: true,
+      ui: true,
+    }
+  }
+}
+
+impl Match3Plugin {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn rows(mut self, rows: usize) -> Self {
+    self.config.rows = rows;
+    self
+  }
+
+  pub fn columns(mut self, columns: usize) -> Self {
+    self.config.columns = columns;
+    self
+  }
+
+  pub fn layout(mut self, layout: BoardLayout) -> Self {
+    self.config.layout = layout;
+    self
+  }
+
+  pub fn cell_size(mut self, cell_size: f32) -> Self {
+    self.config.cell_size = cell_size;
+    self
+  }
+
+  pub fn tile_set(mut self, tile_set: TileSet) -> Self {
+    self.tile_set = tile_set;
+    self
+  }
+
+  pub fn level(mut self, path: &str) -> Self {
+    self.level = Some(path.to_string());
+    self
+  }
+
+  pub fn hint_seconds(mut self, hint_seconds: f32) -> Self {
+    self.config.hint_seconds = hint_seconds;
+    self
+  }
+
+  pub fn seed(mut self, seed: u64) -> Self {
+    self.config.seed = Some(seed);
+    self
+  }
+
+  pub fn drag(mut self, drag: bool) -> Self {
+    self.drag = drag;
+    self
+  }
+
+  pub fn cursor(mut self, cursor: bool) -> Self {
+    self.cursor = cursor;
+    self
+  }
+
+  pub fn hints(mut self, hints: bool) -> Self {
+    self.hints = hints;
+    self
+  }
+
+  pub fn effects(mut self, effects: bool) -> Self {
+    self.effects = effects;
+    self
+  }
+
+  pub fn ui(mut self, ui: bool) -> Self {
+    self.ui = ui;
+    self
+  }
+}
+
+impl Plugin for Match3Plugin {
+  fn build(&self, app: &mut App) {
+    if !app.world.contains_resource::<GameConfig>() {
+      app.insert_resource(self.config);
+    }
+    if !app.world.contains_resource::<TileSet>() {
+      app.insert_resource(self.tile_set.clone());
+    }
+    // The state has to be added before any systems that run on entering or leaving one of its states
+    add_state_to_app(app);
+    app
       .register_type::<TileType>()
+      .register_type::<TileModifier>()
+      .register_type::<BoardLayout>()
       .register_type::<Tile>()
       .register_type::<GameConfig>()
-      .add_startup_systems((setup_camera, setup_grid));
-    add_input_to_app(app);
-    add_drag_to_app(app);
+      .register_type::<TileKind>()
+      .register_type::<TileSet>()
+      .add_startup_system(setup_camera)
+      .add_system(setup_grid.in_schedule(OnExit(GameState::MainMenu)))
+      .add_system(teardown_grid.in_schedule(OnEnter(GameState::MainMenu)));
+    if !app.world.contains_resource::<GameRng>() {
+      let game_rng = GameRng::from_config(app.world.resource::<GameConfig>());
+      app.insert_resource(game_rng);
+    }
+    add_level_to_app(app);
+    if let Some(path) = &self.level {
+      let level = app.world.resource::<AssetServer>().load(path.as_str());
+      app.insert_resource(SelectedLevel(level));
+    }
+    add_swap_to_app(app);
+    if self.drag {
+      add_input_to_app(app);
+      add_drag_to_app(app);
+    }
+    add_resolve_to_app(app);
+    add_score_to_app(app);
+    add_objectives_to_app(app);
+    add_countdown_to_app(app);
+    add_shuffle_to_app(app);
+    add_obstacles_to_app(app);
+    if self.hints {
+      add_hint_to_app(app);
+    }
+    add_tween_to_app(app);
+    if self.effects {
+      add_effects_to_app(app);
+    }
+    if self.cursor {
+      add_cursor_to_app(app);
+    }
+    if self.ui {
+      add_ui_to_app(app);
+    }
+    add_layout_to_app(app);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bevy::{input::{InputPlugin, touch::TouchInput}, window::{CursorLeft, WindowFocused, WindowResized}};
+  // Everything the plugin needs from DefaultPlugins, without opening a window.
+  fn app_with(match3: Match3Plugin) -> App {
+    let mut app = App::new();
+    app
+      .add_plugins(MinimalPlugins)
+      .add_plugin(InputPlugin)
+      .add_plugin(AssetPlugin::default())
+      .add_asset::<Image>()
+      .add_event::<WindowResized>()
+      .add_event::<CursorLeft>()
+      .add_event::<WindowFocused>()
+      .add_event::<TouchInput>()
+      .add_plugin(match3);
+    app
+  }
+  #[test]
+  fn the_game_runs_without_drag_input() {
+    let mut app = app_with(Match3Plugin::new().drag(false).seed(1));
+    app.update();
+    app.world.resource_mut::<NextState<GameState>>().set(GameState::Playing);
+    for _ in 0..5 {
+      app.update();
+    }
+    assert_eq!(app.world.resource::<State<GameState>>().0, GameState::Playing);
+  }
+  #[test]
+  fn a_missing_level_falls_back_to_a_random_board() {
+    let mut app = app_with(Match3Plugin::new().level("levels/missing.level.ron").seed(1));
+    for _ in 0..100 {
+      app.update();
+      if app.world.contains_resource::<FailedLevel>() {
+        break;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(app.world.resource::<FailedLevel>().0, "levels/missing.level.ron");
+    assert!(!app.world.contains_resource::<SelectedLevel>());
+    app.world.resource_mut::<NextState<GameState>>().set(GameState::Playing);
+    app.update();
+    assert_eq!(app.world.resource::<State<GameState>>().0, GameState::Playing);
+  }
+  #[test]
+  fn the_game_runs_without_the_ui() {
+    let mut app = app_with(Match3Plugin::new().ui(false).seed(1));
+    app.update();
+    app.world.resource_mut::<NextState<GameState>>().set(GameState::Playing);
+    for _ in 0..5 {
+      app.update();
+    }
+    assert_eq!(app.world.resource::<State<GameState>>().0, GameState::Playing);
+    assert_eq!(app.world.query::<&ScoreText>().iter(&app.world).count(), 0);
   }
 }
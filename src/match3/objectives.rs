@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+
+use super::{drag::*, level::*, resolve::*, resources::*, state::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectiveProgress {
+  pub objective: Objective,
+  pub progress: u32,
+}
+
+impl ObjectiveProgress {
+  pub fn new(objective: Objective) -> Self {
+    ObjectiveProgress { objective, progress: 0 }
+  }
+
+  pub fn target(&self) -> u32 {
+    match self.objective {
+      Objective::Score(target) | Objective::Clear(_, target) | Objective::Collect(target) => target,
+    }
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self.progress >= self.target()
+  }
+}
+
+impl LevelProgress {
+  pub fn new(level: Option<&Level>) -> Self {
+    let move_limit = level.and_then(|level| match level.limit {
+      Some(Limit::Moves(moves)) => Some(moves),
+      _ => None,
+    });
+    let time_limit = level.and_then(|level| match level.limit {
+      Some(Limit::Seconds(seconds)) => Some(seconds),
+      _ => None,
+    });
+    LevelProgress {
+      move_limit,
+      moves_left: move_limit,
+      time_limit,
+      time_left: time_limit,
+      objectives: level.map_or(vec![], |level| level.objectives.iter().copied().map(ObjectiveProgress::new).collect()),
+    }
+  }
+
+  // How much of the move or time limit is left, from 1 at the start down to 0.
+  // A level without a limit always has all of it left.
+  pub fn remaining(&self) -> f32 {
+    match (self.moves_left, self.move_limit, self.time_left, self.time_limit) {
+      (Some(left), Some(limit), _, _) if limit > 0 => left as f32 / limit as f32,
+      (_, _, Some(left), Some(limit)) if limit > 0.0 => left / limit,
+      _ => 1.0,
+    }
+  }
+
+  pub fn out_of_time(&self) -> bool {
+    self.time_left.is_some_and(|time_left| time_left <= 0.0)
+  }
+
+  // The level is won as soon as every objective is complete, and lost if the moves or the time run out first.
+  // Running out on a level without objectives counts as a win.
+  // None while the level is still going.
+  pub fn outcome(&self) -> Option<LevelResult> {
+    let complete = self.objectives.iter().all(ObjectiveProgress::is_complete);
+    let objectives_met = complete && !self.objectives.is_empty();
+    let out_of_moves = self.moves_left == Some(0);
+    if !objectives_met && !out_of_moves && !self.out_of_time() {
+      return None;
+    }
+    Some(LevelResult {
+      won: complete,
+      stars: if complete { star_rating(self.remaining()) } else { 0 },
+    })
+  }
+}
+
+// Finishing with more of the limit left earns more stars.
+pub fn star_rating(remaining: f32) -> u8 {
+  if remaining >= 0.4 {
+    3
+  } else if remaining >= 0.2 {
+    2
+  } else {
+    1
+  }
+}
+
+pub fn setup_level_progress(
+  selected_level: Option<Res<SelectedLevel>>,
+  levels: Res<Assets<Level>>,
+  mut level_progress: ResMut<LevelProgress>,
+) {
+  let level = selected_level.and_then(|selected_level| levels.get(&selected_level.0));
+  *level_progress = LevelProgress::new(level);
+}
+
+// Only swaps that were accepted use up a move.
+// They are logged so that, with the seed, a game can be replayed.
+pub fn count_moves(
+  mut tile_drag_success_evr: EventReader<TileDragSuccess>,
+  mut level_progress: ResMut<LevelProgress>,
+) {
+  for evt in tile_drag_success_evr.iter() {
+    debug!("Swapped {:?} with {:?}", evt.from, evt.to);
+    if let Some(moves_left) = &mut level_progress.moves_left {
+      *moves_left = moves_left.saturating_sub(1);
+    }
+  }
+}
+
+pub fn track_objectives(
+  score: Res<Score>,
+  mut tiles_cleared_evr: EventReader<TilesCleared>,
+  mut level_progress: ResMut<LevelProgress>,
+) {
+  let events = tiles_cleared_evr.iter().collect::<Vec<_>>();
+  let cleared = events.iter().flat_map(|evt| evt.tiles.iter()).collect::<Vec<_>>();
+  let collected = events.iter().map(|evt| evt.collected.len()).sum::<usize>() as u32;
+  for objective in level_progress.objectives.iter_mut() {
+    match objective.objective {
+      Objective::Score(_) => objective.progress = score.value,
+      Objective::Clear(tile_type, _) => {
+        objective.progress += cleared.iter().filter(|(_, cleared_type)| *cleared_type == tile_type).count() as u32;
+      }
+      Objective::Collect(_) => objective.progress += collected,
+    }
+  }
+}
+
+// The game comes back to Playing once a swap has been fully resolved, so that is when the level can end.
+pub fn check_level_end(
+  mut commands: Commands,
+  level_progress: Res<LevelProgress>,
+  mut next_state: ResMut<NextState<GameState>>,
+) {
+  if let Some(level_result) = level_progress.outcome() {
+    commands.insert_resource(level_result);
+    next_state.set(GameState::Results);
+  }
+}
+
+pub fn add_objectives_to_app(app: &mut App) -> &mut App {
+  app
+    .init_resource::<LevelProgress>()
+    .init_resource::<LevelResult>()
+    .add_system(setup_level_progress.in_schedule(OnExit(GameState::MainMenu)))
+    .add_systems((count_moves, track_objectives))
+    .add_system(check_level_end.in_schedule(OnEnter(GameState::Playing)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::match3::grid::TileType;
+  fn progress(moves_left: u32, objectives: &[(Objective, u32)]) -> LevelProgress {
+    LevelProgress {
+      move_limit: Some(10),
+      moves_left: Some(moves_left),
+      time_limit: None,
+      time_left: None,
+      objectives: objectives.iter().map(|&(objective, progress)| ObjectiveProgress { objective, progress }).collect(),
+    }
+  }
+  #[test]
+  fn level_is_won_once_every_objective_is_complete() {
+    let objectives = [(Objective::Score(500), 600), (Objective::Clear(TileType::Circle, 10), 4)];
+    assert_eq!(progress(5, &objectives).outcome(), None);
+    let objectives = [(Objective::Score(500), 600), (Objective::Clear(TileType::Circle, 10), 10)];
+    assert_eq!(progress(5, &objectives).outcome(), Some(LevelResult { won: true, stars: 3 }));
+    assert_eq!(progress(0, &objectives).outcome(), Some(LevelResult { won: true, stars: 1 }));
+  }
+  #[test]
+  fn level_is_lost_when_the_moves_run_out() {
+    let objectives = [(Objective::Score(500), 200)];
+    assert_eq!(progress(1, &objectives).outcome(), None);
+    assert_eq!(progress(0, &objectives).outcome(), Some(LevelResult { won: false, stars: 0 }));
+  }
+  #[test]
+  fn timed_levels_end_when_the_time_runs_out() {
+    let mut level_progress = LevelProgress {
+      time_limit: Some(60.0),
+      time_left: Some(30.0),
+      objectives: vec![ObjectiveProgress { objective: Objective::Score(500), progress: 500 }],
+      ..Default::default()
+    };
+    assert_eq!(level_progress.outcome(), Some(LevelResult { won: true, stars: 3 }));
+    level_progress.objectives[0].progress = 100;
+    assert_eq!(level_progress.outcome(), None);
+    level_progress.time_left = Some(0.0);
+    assert_eq!(level_progress.outcome(), Some(LevelResult { won: false, stars: 0 }));
+  }
+  #[test]
+  fn collecting_ingredients_counts_towards_the_objective() {
+    let objectives = [(Objective::Collect(3), 2)];
+    assert_eq!(progress(5, &objectives).outcome(), None);
+    let objectives = [(Objective::Collect(3), 3)];
+    assert_eq!(progress(5, &objectives).outcome(), Some(LevelResult { won: true, stars: 3 }));
+  }
+  #[test]
+  fn stars_depend_on_what_is_left() {
+    assert_eq!(star_rating(1.0), 3);
+    assert_eq!(star_rating(0.3), 2);
+    assert_eq!(star_rating(0.1), 1);
+    assert_eq!(LevelProgress::new(None).outcome(), None);
+  }
+}
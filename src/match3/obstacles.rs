@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+use super::{components::*, coords::*, resources::*};
+
+const ICE_COLOR: Color = Color::rgba(0.7, 0.9, 1.0, 0.25);
+const CHAIN_COLOR: Color = Color::rgb(0.35, 0.35, 0.4);
+const STONE_COLOR: Color = Color::rgb(0.5, 0.48, 0.45);
+
+fn obstacle_sprite(color: Color, size: Vec2, transform: Transform) -> impl Bundle {
+  (
+    SpriteBundle {
+      sprite: Sprite {
+        color,
+        custom_size: Some(size),
+        ..Default::default()
+      },
+      transform,
+      ..Default::default()
+    },
+    ObstacleSprite,
+    Name::new("Obstacle"),
+  )
+}
+
+// The obstacles are redrawn from the grid whenever it changes, as children of the board.
+// Ice sits over the tile and gets more opaque with each layer, chains cross the tile and stones fill their cell.
+pub fn sync_obstacle_sprites(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+  boards: Query<(Entity, &GameData), Changed<GameData>>,
+  sprites: Query<Entity, With<ObstacleSprite>>,
+) {
+  let Ok((board, game_data)) = boards.get_single() else {
+    return;
+  };
+  for entity in sprites.iter() {
+    commands.entity(entity).despawn_recursive();
+  }
+  let cell_size = game_config.cell_size;
+  let grid = &game_data.grid;
+  commands.entity(board).with_children(|parent| {
+    for coord in grid.coords() {
+      let obstacles = grid.obstacles(coord);
+      let centre = cell_to_local(coord, cell_size) + Vec3::new(cell_size / 2., cell_size / 2., 0.);
+      if obstacles.stone {
+        parent.spawn(obstacle_sprite(STONE_COLOR, Vec2::splat(cell_size * 0.9), Transform::from_translation(centre)));
+      }
+      if obstacles.ice > 0 {
+        let color = ICE_COLOR.with_a(ICE_COLOR.a() * obstacles.ice as f32);
+        parent.spawn(obstacle_sprite(color, Vec2::splat(cell_size), Transform::from_translation(centre + Vec3::Z * 0.8)));
+      }
+      if obstacles.chain {
+        for angle in [std::f32::consts::FRAC_PI_4, -std::f32::consts::FRAC_PI_4] {
+          let transform = Transform::from_translation(centre + Vec3::Z * 0.9).with_rotation(Quat::from_rotation_z(angle));
+          parent.spawn(obstacle_sprite(CHAIN_COLOR, Vec2::new(cell_size * 1.2, cell_size * 0.12), transform));
+        }
+      }
+    }
+  });
+}
+
+pub fn add_obstacles_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<ObstacleSprite>()
+    .add_system(sync_obstacle_sprites)
+}
@@ -0,0 +1,155 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{bundles::*, components::*, coords::*, grid::*, resources::*, score::*, shapes::*, state::*, system_sets::*, tile_set::*, tween::*};
+
+// Sent once the board has finished resolving and is waiting for the player.
+#[derive(Debug, Clone, Copy)]
+pub struct BoardResolved {
+  pub board: Entity,
+}
+
+// Sent for every step of a resolve with the tiles it cleared and the ingredients it collected, so they can be shown going away.
+// cascade is 1 for the step made by the player's swap, 2 for the first follow-on step, and so on.
+#[derive(Debug, Clone)]
+pub struct TilesCleared {
+  pub board: Entity,
+  pub tiles: Vec<(Coord, TileType)>,
+  pub collected: Vec<Coord>,
+  pub shapes: Vec<Shape>,
+  pub cascade: u32,
+}
+
+const DROP_SECONDS: f32 = 0.25;
+
+// Resolves one step of the cascade at a time. The system only runs once nothing is moving,
+// so each step's tiles finish falling before the next step clears anything.
+#[allow(clippy::too_many_arguments, reason = "a Bevy system takes each resource and query it uses as an argument")]
+pub fn resolve_board(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+  tile_textures: Res<TileTextures>,
+  tile_set: Res<TileSet>,
+  mut game_rng: ResMut<GameRng>,
+  mut score: ResMut<Score>,
+  mut score_changed_evw: EventWriter<ScoreChanged>,
+  mut board_resolved_evw: EventWriter<BoardResolved>,
+  mut tiles_cleared_evw: EventWriter<TilesCleared>,
+  mut game_data: Query<(Entity, &mut GameData, &mut ResolveBoard)>,
+  mut tiles: Query<(Entity, &mut Tile, &Transform, &mut Name)>,
+) {
+  for (board, mut game_data, mut resolve_board) in game_data.iter_mut() {
+    let GameData { grid, specials } = &mut *game_data;
+    let swap = resolve_board.swap.take();
+    let Some(step) = resolve_next_step(grid, specials, swap, &tile_set, &mut game_rng.rng) else {
+      commands.entity(board).remove::<ResolveBoard>();
+      board_resolved_evw.send(BoardResolved { board });
+      continue;
+    };
+    resolve_board.cascade += 1;
+    let cascade = resolve_board.cascade;
+
+    let breakdown = score_step(&step, cascade);
+    score.value += breakdown.total;
+    score_changed_evw.send(ScoreChanged {
+      score: score.value,
+      breakdown,
+    });
+
+    let mut slots = tiles
+      .iter()
+      .map(|(entity, tile, _, _)| ((tile.column, tile.row), entity))
+      .collect::<HashMap<Coord, Entity>>();
+    let removed = step.removed.iter().map(|(coord, _)| coord).chain(step.collected.iter());
+    for coord in removed {
+      if let Some(entity) = slots.remove(coord) {
+        commands.entity(entity).despawn_recursive();
+      }
+    }
+    for drop in step.drops.iter() {
+      if let Some(entity) = slots.remove(&drop.from) {
+        slots.insert(drop.to, entity);
+      }
+    }
+
+    for ((column, row), entity) in slots {
+      if let Ok((_, mut tile, transform, mut name)) = tiles.get_mut(entity) {
+        let modifier = specials.get(&(column, row)).copied();
+        if tile.modifier != modifier {
+          tile.modifier = modifier;
+          commands.entity(entity).despawn_descendants();
+          if let Some(modifier) = modifier {
+            let marker = commands.spawn(SpecialMarkerBundle::new(modifier, game_config.cell_size)).id();
+            commands.entity(entity).add_child(marker);
+          }
+        }
+        if tile.column == column && tile.row == row {
+          continue;
+        }
+        tile.column = column;
+        tile.row = row;
+        commands.entity(entity).insert(Tween::new(
+          transform.translation,
+          cell_to_local((column, row), game_config.cell_size),
+          DROP_SECONDS,
+          Easing::EaseIn,
+        ));
+        name.set(format!("Tile {} {}", column, row));
+      }
+    }
+
+    // New tiles start stacked above the top of their column and fall into place.
+    let mut new_tiles = HashMap::<usize, usize>::new();
+    for &((column, _), _) in step.spawned.iter() {
+      *new_tiles.entry(column).or_default() += 1;
+    }
+    for &((column, row), tile_type) in step.spawned.iter() {
+      let modifier = specials.get(&(column, row)).copied();
+      let mut bundle = TileBundle::new(
+        Tile { tile_type, column, row, modifier },
+        game_config.cell_size,
+        &tile_textures,
+      );
+      let end = bundle.sprite.transform.translation;
+      let start = end + Vec3::new(0.0, new_tiles[&column] as f32 * game_config.cell_size, 0.0);
+      bundle.sprite.transform.translation = start;
+      let tile = commands.spawn((bundle, Tween::new(start, end, DROP_SECONDS, Easing::EaseIn))).id();
+      if let Some(modifier) = modifier {
+        let marker = commands.spawn(SpecialMarkerBundle::new(modifier, game_config.cell_size)).id();
+        commands.entity(tile).add_child(marker);
+      }
+      commands.entity(board).add_child(tile);
+    }
+
+    tiles_cleared_evw.send(TilesCleared {
+      board,
+      tiles: step.removed,
+      collected: step.collected,
+      shapes: step.shapes,
+      cascade,
+    });
+  }
+}
+
+type BusyBoard = Or<(With<ResolveBoard>, With<ShuffleBoard>)>;
+
+// Hand the board back to the player once it has been resolved and nothing is moving any more.
+pub fn finish_resolving(
+  mut next_state: ResMut<NextState<GameState>>,
+  boards: Query<(), BusyBoard>,
+  tweens: Query<(), With<Tween>>,
+) {
+  if boards.is_empty() && tweens.is_empty() {
+    next_state.set(GameState::Playing);
+  }
+}
+
+pub fn add_resolve_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<ResolveBoard>()
+    .register_type::<SpecialMarker>()
+    .add_event::<BoardResolved>()
+    .add_event::<TilesCleared>()
+    .configure_set(Resolve.after(Cleanup))
+    .add_system(resolve_board.in_set(Resolve).run_if(in_state(GameState::Resolving).and_then(tweens_finished)))
+    .add_system(finish_resolving.after(Shuffle).run_if(in_state(GameState::Resolving)))
+}
@@ -1,17 +1,110 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 
-use super::grid::*;
+use super::{grid::*, objectives::*, specials::*, tile_set::*};
 
 #[derive(Resource, Default, Clone, Copy, Debug, Reflect)]
 pub struct GameConfig {
   pub rows: usize,
   pub columns: usize,
+  // The outline of the board within the rows and columns
+  pub layout: BoardLayout,
   pub cell_size: f32,
+  // How long the player can be idle before a hint is shown
+  pub hint_seconds: f32,
+  // Seed for the board, refills and shuffles. A random seed is picked if this is None.
+  pub seed: Option<u64>,
 }
 
 #[derive(Component, Default, Debug, Reflect)]
 pub struct GameData {
-  pub grid: Vec<Vec<Option<TileType>>>,
+  pub grid: Grid,
+  #[reflect(ignore)]
+  pub specials: Specials,
 }
 
+#[derive(Resource, Default, Debug)]
+pub struct TileTextures {
+  pub textures: HashMap<TileType, Handle<Image>>,
+  pub tints: HashMap<TileType, Color>,
+}
+
+impl TileTextures {
+  // Every kind of tile gets a texture, whether or not the tile set has it, so any tile can be drawn.
+  // The tile set's own kinds come last so their textures and tints win.
+  pub fn load(tile_set: &TileSet, asset_server: &AssetServer) -> Self {
+    let all = TileSet::all();
+    let ingredient = TileKind::ingredient();
+    let kinds = all.kinds.iter().chain([&ingredient]).chain(tile_set.kinds.iter());
+    TileTextures {
+      textures: kinds.clone().map(|kind| (kind.tile_type, asset_server.load(kind.texture.as_str()))).collect(),
+      tints: kinds.map(|kind| (kind.tile_type, kind.tint)).collect(),
+    }
+  }
+
+  pub fn get(&self, tile_type: TileType) -> Handle<Image> {
+    self.textures.get(&tile_type).unwrap().clone()
+  }
+
+  pub fn tint(&self, tile_type: TileType) -> Color {
+    self.tints.get(&tile_type).copied().unwrap_or(Color::WHITE)
+  }
+}
+
+#[derive(Resource, Default, Clone, Copy, Debug, Reflect)]
+pub struct Score {
+  pub value: u32,
+}
+
+// The finger currently dragging a tile. Other fingers are ignored until it is lifted.
+#[derive(Resource, Default, Debug)]
+pub struct ActiveTouch {
+  pub id: Option<u64>,
+  // Relative to the board
+  pub last_position: Vec2,
+}
 
+#[derive(Resource, Debug)]
+pub struct HintTimer {
+  pub timer: Timer,
+}
+
+// How far the player has got through the selected level.
+// Without a level there is no limit and no objectives, so the game only ends when the board is stuck.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct LevelProgress {
+  pub move_limit: Option<u32>,
+  pub moves_left: Option<u32>,
+  // The countdown for timed levels, in seconds
+  pub time_limit: Option<f32>,
+  pub time_left: Option<f32>,
+  pub objectives: Vec<ObjectiveProgress>,
+}
+
+// How the level ended, shown in GameState::Results
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelResult {
+  pub won: bool,
+  // 1 to 3 for a win, 0 for a loss
+  pub stars: u8,
+}
+
+// Every random draw in the game comes from here, so the same seed and the same moves always give the same game.
+#[derive(Resource, Debug)]
+pub struct GameRng {
+  pub seed: u64,
+  pub rng: StdRng,
+}
+
+impl GameRng {
+  pub fn new(seed: u64) -> Self {
+    GameRng {
+      seed,
+      rng: StdRng::seed_from_u64(seed),
+    }
+  }
+
+  pub fn from_config(game_config: &GameConfig) -> Self {
+    GameRng::new(game_config.seed.unwrap_or_else(|| rand::thread_rng().gen()))
+  }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use super::{grid::*, resources::*, shapes::*, state::*};
+
+pub const LINE_3_POINTS: u32 = 50;
+pub const LINE_4_POINTS: u32 = 100;
+// Worth more than any crossing shape, as five in a row is the rarest shape and makes a colour bomb
+pub const LINE_5_POINTS: u32 = 500;
+pub const INTERSECTION_BONUS: u32 = 100;
+pub const SPECIAL_CLEAR_POINTS: u32 = 20;
+
+// The points awarded for one step of a resolve.
+// The multiplier is the cascade depth: 1 for the swap itself, 2 for the first follow-on match, and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect)]
+pub struct ScoreBreakdown {
+  pub shape_points: u32,
+  pub intersection_bonus: u32,
+  pub special_points: u32,
+  pub multiplier: u32,
+  pub total: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreChanged {
+  pub score: u32,
+  pub breakdown: ScoreBreakdown,
+}
+
+pub fn shape_points(shape: &[(usize, usize)]) -> u32 {
+  match shape.len() {
+    0..=2 => 0,
+    3 => LINE_3_POINTS,
+    4 => LINE_4_POINTS,
+    _ => LINE_5_POINTS,
+  }
+}
+
+// L, T and plus shapes are made of a horizontal and a vertical run crossing each other.
+pub fn intersection_bonus(shape: &Shape) -> u32 {
+  match shape.kind {
+    ShapeKind::L | ShapeKind::T | ShapeKind::Plus => INTERSECTION_BONUS,
+    _ => 0,
+  }
+}
+
+// A crossing shape scores each of its runs, plus the intersection bonus.
+pub fn score_shapes(shapes: &[Shape], cascade: u32) -> ScoreBreakdown {
+  let shape_points = shapes.iter().flat_map(|shape| shape.runs.iter()).map(|run| shape_points(run)).sum();
+  let intersection_bonus = shapes.iter().map(intersection_bonus).sum();
+  let multiplier = cascade.max(1);
+  ScoreBreakdown {
+    shape_points,
+    intersection_bonus,
+    special_points: 0,
+    multiplier,
+    total: (shape_points + intersection_bonus) * multiplier,
+  }
+}
+
+// Shapes score as usual, and every other tile cleared by a special tile is worth a few points on top.
+pub fn score_step(step: &ResolveStep, cascade: u32) -> ScoreBreakdown {
+  let mut breakdown = score_shapes(&step.shapes, cascade);
+  let shape_tiles = step.shapes.iter().map(|shape| shape.tiles.len()).sum::<usize>();
+  breakdown.special_points = step.removed.len().saturating_sub(shape_tiles) as u32 * SPECIAL_CLEAR_POINTS;
+  breakdown.total += breakdown.special_points * breakdown.multiplier;
+  breakdown
+}
+
+pub fn reset_score(
+  mut score: ResMut<Score>,
+) {
+  score.value = 0;
+}
+
+pub fn add_score_to_app(app: &mut App) -> &mut App {
+  app
+    .init_resource::<Score>()
+    .register_type::<Score>()
+    .add_event::<ScoreChanged>()
+    .add_system(reset_score.in_schedule(OnExit(GameState::MainMenu)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn shape_points_depend_on_length() {
+    assert_eq!(shape_points(&[(0, 0), (0, 1), (0, 2)]), LINE_3_POINTS);
+    assert_eq!(shape_points(&[(0, 0), (0, 1), (0, 2), (0, 3)]), LINE_4_POINTS);
+    assert_eq!(shape_points(&[(0, 0), (0, 1), (0, 2), (0, 3), (0, 4)]), LINE_5_POINTS);
+    assert_eq!(shape_points(&[(0, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5)]), LINE_5_POINTS);
+  }
+  #[test]
+  fn score_shapes_adds_bonus_for_crossing_shapes() {
+    let shapes = classify_shapes(&[
+      vec![(0, 0), (0, 1), (0, 2)],
+      vec![(0, 0), (1, 0), (2, 0)],
+      vec![(4, 3), (4, 4), (4, 5)],
+    ]);
+    let breakdown = score_shapes(&shapes, 1);
+    assert_eq!(breakdown.shape_points, LINE_3_POINTS * 3);
+    assert_eq!(breakdown.intersection_bonus, INTERSECTION_BONUS);
+    assert_eq!(breakdown.total, LINE_3_POINTS * 3 + INTERSECTION_BONUS);
+  }
+  #[test]
+  fn five_in_a_row_beats_crossing_shapes() {
+    let line5 = score_shapes(&classify_shapes(&[vec![(0, 0), (0, 1), (0, 2), (0, 3), (0, 4)]]), 1);
+    let plus = score_shapes(&classify_shapes(&[
+      vec![(0, 1), (1, 1), (2, 1), (3, 1)],
+      vec![(1, 0), (1, 1), (1, 2), (1, 3)],
+    ]), 1);
+    assert!(line5.total > plus.total);
+  }
+  #[test]
+  fn score_shapes_multiplies_by_cascade_depth() {
+    let shapes = classify_shapes(&[vec![(0, 0), (0, 1), (0, 2), (0, 3)]]);
+    let breakdown = score_shapes(&shapes, 3);
+    assert_eq!(breakdown.multiplier, 3);
+    assert_eq!(breakdown.total, LINE_4_POINTS * 3);
+  }
+}
@@ -1,43 +1,31 @@
-use bevy::{prelude::*, window::PrimaryWindow, utils::HashMap, sprite::Anchor};
+use bevy::{prelude::*, sprite::Anchor};
 
-use super::{resources::{GameConfig, GameData}, grid::*, bundles::*, components::*};
+use super::{resources::{GameConfig, GameData, GameRng, TileTextures}, grid::*, bundles::*, components::*, level::*, tile_set::*};
 
+// fit_camera_to_board puts the camera over the board once the window size is known.
 pub fn setup_camera(
     mut commands: Commands,
-    window: Query<&Window, With<PrimaryWindow>>,
 ){
-    let primary_window = window.get_single().unwrap();
-    commands
-      .spawn(Camera2dBundle{
-        transform: Transform::from_xyz(primary_window.width() / 2., primary_window.height() / 2., 100.0),
-        ..Default::default()
-      });
-
+    commands.spawn(Camera2dBundle::default());
 }
 
 pub fn setup_grid(
     mut commands: Commands,
     game_config: Res<GameConfig>,
+    tile_set: Res<TileSet>,
+    mut game_rng: ResMut<GameRng>,
     asset_server: Res<AssetServer>,
+    selected_level: Option<Res<SelectedLevel>>,
+    levels: Res<Assets<Level>>,
   ) {
       
-    let triangle_handle = asset_server.load("triangle.png");
-    let pentagon_handle = asset_server.load("pentagon.png");
-    let square_handle = asset_server.load("square.png");
-    let circle_handle = asset_server.load("circle.png");
-    let diamond_handle = asset_server.load("diamond.png");
-    let star_handle = asset_server.load("star.png");
-  
-    let tile_textures = vec![
-      (TileType::Pentagon, pentagon_handle),
-      (TileType::Triangle, triangle_handle),
-      (TileType::Square, square_handle),
-      (TileType::Circle, circle_handle),
-      (TileType::Diamond, diamond_handle),
-      (TileType::Star, star_handle),
-    ].into_iter().collect::<HashMap<TileType,Handle<Image>>>();
+    let tile_textures = TileTextures::load(&tile_set, &asset_server);
     
-    let grid = create_grid(game_config.rows.into(), game_config.columns.into());
+    info!("Creating grid with seed {}", game_rng.seed);
+    let grid = match selected_level.and_then(|selected_level| levels.get(&selected_level.0)) {
+      Some(level) => fill_grid(&level.template, &tile_set, &mut game_rng.rng),
+      None => create_grid(&game_config.layout.mask(game_config.columns, game_config.rows), &tile_set, &mut game_rng.rng),
+    };
     commands.spawn(GameBundle{
       game_data: GameData {
         grid: grid.clone(),
@@ -59,29 +47,26 @@ pub fn setup_grid(
       ..Default::default()
     })
       .with_children(|commands|{
-        let custom_size = Some(Vec2::new(game_config.cell_size, game_config.cell_size));
         for column in 0..game_config.columns {
           for row in 0..game_config.rows {
-            if let Some(tile_type) = grid[column][row] {
-              let y = (row as f32) * game_config.cell_size;
-              let x = (column as f32) * game_config.cell_size;
-              commands.spawn(TileBundle {
-                sprite: SpriteBundle {
-                  transform: Transform::from_xyz(x, y, 0.0),
-                  texture: tile_textures.get(&tile_type).unwrap().clone(),
-                  sprite: Sprite {
-                      custom_size,
-                      anchor: Anchor::BottomLeft,
-                      ..Default::default()
-                  },
-                  ..Default::default()
-                },
-                tile: Tile { tile_type, column, row, },
-                name: Name::new(format!("Tile {} {}", column, row)),
-              });
+            if let Some(tile_type) = grid.get((column, row)) {
+              commands.spawn(TileBundle::new(
+                Tile { tile_type, column, row, modifier: None },
+                game_config.cell_size,
+                &tile_textures,
+              ));
             }
           }
         }  
       });
+    commands.insert_resource(tile_textures);
+  }
+
+pub fn teardown_grid(
+    mut commands: Commands,
+    boards: Query<Entity, With<GameData>>,
+  ) {
+    for entity in boards.iter() {
+      commands.entity(entity).despawn_recursive();
+    }
   }
-  
\ No newline at end of file
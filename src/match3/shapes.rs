@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum ShapeKind {
+  Line3,
+  Line4,
+  Line5,
+  L,
+  T,
+  Plus,
+}
+
+// A compound shape made of one or more runs from find_shapes that share tiles.
+// The pivot is the tile where the runs cross, or the middle of the run for a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shape {
+  pub kind: ShapeKind,
+  pub tiles: Vec<(usize, usize)>,
+  pub pivot: (usize, usize),
+  pub runs: Vec<Vec<(usize, usize)>>,
+}
+
+impl ShapeKind {
+  fn rank(&self) -> u8 {
+    match self {
+      ShapeKind::Line3 => 0,
+      ShapeKind::Line4 => 1,
+      ShapeKind::L => 2,
+      ShapeKind::T => 3,
+      ShapeKind::Plus => 4,
+      ShapeKind::Line5 => 5,
+    }
+  }
+}
+
+fn is_run_end(run: &[(usize, usize)], coord: (usize, usize)) -> bool {
+  run.first() == Some(&coord) || run.last() == Some(&coord)
+}
+
+fn classify_group(runs: &[&Vec<(usize, usize)>]) -> Shape {
+  let mut tiles = vec![];
+  for run in runs {
+    for coord in run.iter() {
+      if !tiles.contains(coord) {
+        tiles.push(*coord);
+      }
+    }
+  }
+
+  let longest = runs.iter().max_by_key(|run| run.len()).unwrap();
+  let mut kind = match longest.len() {
+    0..=3 => ShapeKind::Line3,
+    4 => ShapeKind::Line4,
+    _ => ShapeKind::Line5,
+  };
+  let mut pivot = longest[longest.len() / 2];
+  let runs_in_shape = runs.iter().map(|run| run.to_vec()).collect::<Vec<_>>();
+  if kind == ShapeKind::Line5 {
+    return Shape { kind, tiles, pivot, runs: runs_in_shape };
+  }
+
+  // Where two runs cross, the position of the shared tile in each run tells us the shape:
+  // the end of both is an L, the end of one is a T, and the middle of both is a plus.
+  for (index, run) in runs.iter().enumerate() {
+    for other in runs.iter().skip(index + 1) {
+      if let Some(shared) = run.iter().find(|coord| other.contains(coord)) {
+        let crossing_kind = match (is_run_end(run, *shared), is_run_end(other, *shared)) {
+          (true, true) => ShapeKind::L,
+          (false, false) => ShapeKind::Plus,
+          _ => ShapeKind::T,
+        };
+        if crossing_kind.rank() > kind.rank() {
+          kind = crossing_kind;
+          pivot = *shared;
+        }
+      }
+    }
+  }
+  Shape { kind, tiles, pivot, runs: runs_in_shape }
+}
+
+// Group the runs returned by find_shapes into compound shapes.
+// Runs end up in the same group when they share a tile, directly or through another run.
+pub fn classify_shapes(runs: &[Vec<(usize, usize)>]) -> Vec<Shape> {
+  let mut groups: Vec<Vec<usize>> = vec![];
+  for (index, run) in runs.iter().enumerate() {
+    let mut group = vec![index];
+    let mut remaining = vec![];
+    for existing in groups {
+      if existing.iter().any(|&other| runs[other].iter().any(|coord| run.contains(coord))) {
+        group.extend(existing);
+      } else {
+        remaining.push(existing);
+      }
+    }
+    remaining.push(group);
+    groups = remaining;
+  }
+
+  groups.sort_by_key(|group| *group.iter().min().unwrap());
+  groups
+    .into_iter()
+    .map(|mut group| {
+      group.sort();
+      classify_group(&group.iter().map(|&index| &runs[index]).collect::<Vec<_>>())
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn classify_shapes_labels_lines_by_length() {
+    let runs = vec![
+      vec![(0, 0), (0, 1), (0, 2)],
+      vec![(2, 0), (2, 1), (2, 2), (2, 3)],
+      vec![(4, 0), (4, 1), (4, 2), (4, 3), (4, 4)],
+    ];
+    let shapes = classify_shapes(&runs);
+    assert_eq!(shapes.iter().map(|shape| shape.kind).collect::<Vec<_>>(), vec![
+      ShapeKind::Line3,
+      ShapeKind::Line4,
+      ShapeKind::Line5,
+    ]);
+    assert_eq!(shapes[0].pivot, (0, 1));
+  }
+  #[test]
+  fn classify_shapes_merges_crossing_runs() {
+    let l_runs = vec![
+      vec![(0, 0), (0, 1), (0, 2)],
+      vec![(0, 0), (1, 0), (2, 0)],
+    ];
+    let l = classify_shapes(&l_runs);
+    assert_eq!(l.len(), 1);
+    assert_eq!(l[0].kind, ShapeKind::L);
+    assert_eq!(l[0].pivot, (0, 0));
+    assert_eq!(l[0].tiles.len(), 5);
+
+    let t_runs = vec![
+      vec![(0, 2), (1, 2), (2, 2)],
+      vec![(1, 0), (1, 1), (1, 2)],
+    ];
+    let t = classify_shapes(&t_runs);
+    assert_eq!(t[0].kind, ShapeKind::T);
+    assert_eq!(t[0].pivot, (1, 2));
+
+    let plus_runs = vec![
+      vec![(0, 1), (1, 1), (2, 1)],
+      vec![(1, 0), (1, 1), (1, 2)],
+    ];
+    let plus = classify_shapes(&plus_runs);
+    assert_eq!(plus[0].kind, ShapeKind::Plus);
+    assert_eq!(plus[0].pivot, (1, 1));
+  }
+  #[test]
+  fn classify_shapes_prefers_five_in_a_row() {
+    let runs = vec![
+      vec![(0, 2), (1, 2), (2, 2), (3, 2), (4, 2)],
+      vec![(2, 0), (2, 1), (2, 2)],
+    ];
+    let shapes = classify_shapes(&runs);
+    assert_eq!(shapes.len(), 1);
+    assert_eq!(shapes[0].kind, ShapeKind::Line5);
+    assert_eq!(shapes[0].tiles.len(), 7);
+  }
+}
@@ -0,0 +1,93 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{components::*, coords::*, grid::*, resolve::*, resources::*, state::*, system_sets::*, tween::*};
+
+const SHUFFLE_SECONDS: f32 = 0.5;
+
+#[derive(Debug, Clone)]
+pub struct BoardShuffled {
+  pub board: Entity,
+  pub moves: Vec<TileMove>,
+}
+
+pub fn check_for_moves(
+  mut commands: Commands,
+  mut board_resolved_evr: EventReader<BoardResolved>,
+  mut game_data: Query<&mut GameData>,
+) {
+  for evt in board_resolved_evr.iter() {
+    if let Ok(mut game_data) = game_data.get_mut(evt.board) {
+      let GameData { grid, specials } = &mut *game_data;
+      if !has_moves(grid, specials) {
+        commands.entity(evt.board).insert(ShuffleBoard);
+      }
+    }
+  }
+}
+
+pub fn shuffle_board(
+  mut commands: Commands,
+  mut game_rng: ResMut<GameRng>,
+  mut game_data: Query<(Entity, &mut GameData), With<ShuffleBoard>>,
+  mut board_shuffled_evw: EventWriter<BoardShuffled>,
+  mut next_state: ResMut<NextState<GameState>>,
+) {
+  for (board, mut game_data) in game_data.iter_mut() {
+    commands.entity(board).remove::<ShuffleBoard>();
+    let GameData { grid, specials } = &mut *game_data;
+    let Some(moves) = shuffle_grid(grid, specials, &mut game_rng.rng) else {
+      info!("Could not find a shuffle with a move left");
+      next_state.set(GameState::GameOver);
+      continue;
+    };
+    // The shuffle can put an ingredient in the bottom row, so the board is resolved again to collect it
+    if !ingredients_at_bottom(grid).is_empty() {
+      commands.entity(board).insert(ResolveBoard::default());
+    }
+    board_shuffled_evw.send(BoardShuffled { board, moves });
+  }
+}
+
+// Moves the board's tiles to where the shuffle put them.
+pub fn animate_shuffle(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+  mut board_shuffled_evr: EventReader<BoardShuffled>,
+  boards: Query<&Children>,
+  mut tiles: Query<(&mut Tile, &Transform, &mut Name)>,
+) {
+  for evt in board_shuffled_evr.iter() {
+    let Ok(children) = boards.get(evt.board) else {
+      continue;
+    };
+    let tile_entities = children
+      .iter()
+      .filter_map(|&entity| tiles.get(entity).ok().map(|(tile, _, _)| ((tile.column, tile.row), entity)))
+      .collect::<HashMap<(usize, usize), Entity>>();
+    for tile_move in evt.moves.iter() {
+      let Some(&entity) = tile_entities.get(&tile_move.from) else {
+        continue;
+      };
+      if let Ok((mut tile, transform, mut name)) = tiles.get_mut(entity) {
+        let (column, row) = tile_move.to;
+        tile.column = column;
+        tile.row = row;
+        name.set(format!("Tile {} {}", column, row));
+        commands.entity(entity).insert(Tween::new(
+          transform.translation,
+          cell_to_local((column, row), game_config.cell_size),
+          SHUFFLE_SECONDS,
+          Easing::SmoothStep,
+        ));
+      }
+    }
+  }
+}
+
+pub fn add_shuffle_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<ShuffleBoard>()
+    .add_event::<BoardShuffled>()
+    .configure_set(Shuffle.after(Resolve).run_if(not(in_state(GameState::Paused))))
+    .add_systems((check_for_moves, shuffle_board.run_if(tweens_finished), animate_shuffle).chain().in_set(Shuffle))
+}
@@ -0,0 +1,343 @@
+use bevy::utils::HashMap;
+
+use super::{grid::*, shapes::*};
+
+// Special tiles keep their colour in the grid, so they still match like any other tile.
+// The modifier for each special tile is kept alongside the grid, keyed by (column, row).
+pub type Specials = HashMap<Coord, TileModifier>;
+
+// Tiles cleared by swapping a special tile, before any shapes are taken into account.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Activation {
+  pub cleared: Vec<Coord>,
+  pub triggered: Vec<(Coord, TileModifier)>,
+}
+
+pub fn special_for_shape(shape: &Shape) -> Option<TileModifier> {
+  match shape.kind {
+    ShapeKind::Line3 => None,
+    ShapeKind::Line4 => {
+      let row = shape.tiles[0].1;
+      if shape.tiles.iter().all(|coord| coord.1 == row) {
+        Some(TileModifier::StripedHorizontal)
+      } else {
+        Some(TileModifier::StripedVertical)
+      }
+    }
+    ShapeKind::L | ShapeKind::T | ShapeKind::Plus => Some(TileModifier::Bomb),
+    ShapeKind::Line5 => Some(TileModifier::ColorBomb),
+  }
+}
+
+// A special tile made by a swap appears where the swapped tile landed,
+// otherwise it appears at the pivot of the shape.
+pub fn special_location(shape: &Shape, swapped: &[Coord]) -> Coord {
+  swapped
+    .iter()
+    .find(|coord| shape.tiles.contains(coord))
+    .copied()
+    .unwrap_or(shape.pivot)
+}
+
+// The tiles cleared when a special tile at coord goes off, including the special tile itself.
+pub fn blast(grid: &Grid, coord: Coord, modifier: TileModifier) -> Vec<Coord> {
+  let (column, row) = coord;
+  let mut cleared = vec![];
+  match modifier {
+    TileModifier::StripedHorizontal => {
+      cleared.extend(grid.row(row).map(|(coord, _)| coord));
+    }
+    TileModifier::StripedVertical => {
+      cleared.extend(grid.column(column).map(|(coord, _)| coord));
+    }
+    TileModifier::Bomb => {
+      cleared.extend(area_around(grid, coord, 1));
+    }
+    TileModifier::ColorBomb => {
+      cleared.push(coord);
+      if let Some(tile_type) = grid.get(coord) {
+        cleared.extend(tiles_of_type(grid, tile_type));
+      }
+    }
+  }
+  cleared
+}
+
+pub fn tiles_of_type(grid: &Grid, tile_type: TileType) -> Vec<Coord> {
+  grid.coords().filter(|&coord| grid.get(coord) == Some(tile_type)).collect()
+}
+
+// Any special tile caught in the cleared area goes off as well, which can set off others.
+// Returns every cleared coordinate once, along with the special tiles that went off.
+pub fn expand_cleared(
+  grid: &Grid,
+  specials: &Specials,
+  cleared: Vec<Coord>,
+) -> (Vec<Coord>, Vec<(Coord, TileModifier)>) {
+  let mut expanded: Vec<Coord> = vec![];
+  let mut triggered = vec![];
+  let mut queue = cleared;
+  queue.reverse();
+  while let Some(coord) = queue.pop() {
+    if expanded.contains(&coord) {
+      continue;
+    }
+    expanded.push(coord);
+    if let Some(&modifier) = specials.get(&coord) {
+      triggered.push((coord, modifier));
+      let mut blasted = blast(grid, coord, modifier);
+      blasted.reverse();
+      queue.splice(0..0, blasted);
+    }
+  }
+  (expanded, triggered)
+}
+
+// What happens when two special tiles are swapped with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combination {
+  // Clears the row and the column of the swap
+  Cross,
+  // Clears three rows and three columns around the swap
+  ThickCross,
+  // Clears the 5x5 area around the swap
+  BigBomb,
+  // Every tile of the other special's colour becomes that special and goes off
+  Spread(TileModifier),
+  // Clears the whole board
+  ClearBoard,
+}
+
+pub fn combine_specials(first: TileModifier, second: TileModifier) -> Combination {
+  use TileModifier::*;
+  match (first, second) {
+    (ColorBomb, ColorBomb) => Combination::ClearBoard,
+    (ColorBomb, other) | (other, ColorBomb) => Combination::Spread(other),
+    (Bomb, Bomb) => Combination::BigBomb,
+    (Bomb, _) | (_, Bomb) => Combination::ThickCross,
+    _ => Combination::Cross,
+  }
+}
+
+fn lines_through(grid: &Grid, center: Coord, radius: usize) -> Vec<Coord> {
+  grid
+    .coords()
+    .filter(|&(column, row)| column.abs_diff(center.0) <= radius || row.abs_diff(center.1) <= radius)
+    .collect()
+}
+
+fn area_around(grid: &Grid, center: Coord, radius: usize) -> Vec<Coord> {
+  grid
+    .coords()
+    .filter(|&(column, row)| column.abs_diff(center.0) <= radius && row.abs_diff(center.1) <= radius)
+    .collect()
+}
+
+// Apply a combination centred on the tile the player dragged onto.
+// Spreading a special marks the affected tiles in specials so they go off when the activation is expanded.
+// Tiles that are already special keep their own, and the special that was swapped with the colour bomb goes off where it is.
+pub fn apply_combination(
+  grid: &Grid,
+  specials: &mut Specials,
+  combination: Combination,
+  center: Coord,
+  other: Coord,
+) -> Vec<Coord> {
+  match combination {
+    Combination::Cross => lines_through(grid, center, 0),
+    Combination::ThickCross => lines_through(grid, center, 1),
+    Combination::BigBomb => area_around(grid, center, 2),
+    Combination::ClearBoard => grid.coords().collect(),
+    Combination::Spread(modifier) => {
+      let mut cleared = vec![center];
+      cleared.extend(blast(grid, other, modifier));
+      if let Some(tile_type) = grid.get(other) {
+        for coord in tiles_of_type(grid, tile_type).into_iter().filter(|&coord| coord != other) {
+          specials.entry(coord).or_insert(modifier);
+          cleared.push(coord);
+        }
+      }
+      cleared
+    }
+  }
+}
+
+// Swapping two special tiles sets off their combination, and a colour bomb goes off as soon as it is swapped,
+// clearing every tile of the colour it was swapped with.
+// The swap has already been applied to the grid and the specials, so tile2 holds the tile the player dragged.
+pub fn activate_swap(
+  grid: &Grid,
+  specials: &mut Specials,
+  tile1: Coord,
+  tile2: Coord,
+) -> Option<Activation> {
+  if let (Some(&first), Some(&second)) = (specials.get(&tile2), specials.get(&tile1)) {
+    specials.remove(&tile1);
+    specials.remove(&tile2);
+    let combination = combine_specials(first, second);
+    // The special that isn't a colour bomb decides what gets spread.
+    let other = if first == TileModifier::ColorBomb { tile1 } else { tile2 };
+    return Some(Activation {
+      cleared: apply_combination(grid, specials, combination, tile2, other),
+      triggered: vec![(tile2, first), (tile1, second)],
+    });
+  }
+  for (bomb, other) in [(tile1, tile2), (tile2, tile1)] {
+    if specials.get(&bomb) == Some(&TileModifier::ColorBomb) {
+      specials.remove(&bomb);
+      let mut cleared = vec![bomb];
+      if let Some(tile_type) = grid.get(other).filter(|tile_type| tile_type.is_matchable()) {
+        cleared.extend(tiles_of_type(grid, tile_type));
+      }
+      return Some(Activation {
+        cleared,
+        triggered: vec![(bomb, TileModifier::ColorBomb)],
+      });
+    }
+  }
+  None
+}
+
+// Swaps that set off a special tile are allowed even if they don't make a shape.
+pub fn activates_on_swap(specials: &Specials, tile1: Coord, tile2: Coord) -> bool {
+  matches!(
+    (specials.get(&tile1), specials.get(&tile2)),
+    (Some(_), Some(_)) | (Some(TileModifier::ColorBomb), _) | (_, Some(TileModifier::ColorBomb))
+  )
+}
+
+// A colour bomb can always be swapped, and so can two special tiles next to each other, as long as neither is locked.
+pub fn has_special_moves(grid: &Grid, specials: &Specials) -> bool {
+  specials.iter().any(|(&coord, &modifier)| {
+    !grid.is_locked(coord) && grid.neighbours(coord).any(|(_, neighbour)| {
+      !grid.is_locked(neighbour) && (modifier == TileModifier::ColorBomb || specials.contains_key(&neighbour))
+    })
+  })
+}
+
+pub fn swap_specials(specials: &mut Specials, tile1: Coord, tile2: Coord) {
+  let special1 = specials.remove(&tile1);
+  let special2 = specials.remove(&tile2);
+  if let Some(modifier) = special1 {
+    specials.insert(tile2, modifier);
+  }
+  if let Some(modifier) = special2 {
+    specials.insert(tile1, modifier);
+  }
+}
+
+// Special tiles fall with the tiles they belong to.
+pub fn move_specials(specials: &mut Specials, drops: &Vec<TileMove>) {
+  for drop in drops {
+    if let Some(modifier) = specials.remove(&drop.from) {
+      specials.insert(drop.to, modifier);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn special_for_shape_matches_shape_kind() {
+    let line4 = classify_shapes(&[vec![(0, 1), (1, 1), (2, 1), (3, 1)]]);
+    assert_eq!(special_for_shape(&line4[0]), Some(TileModifier::StripedHorizontal));
+    let line4 = classify_shapes(&[vec![(1, 0), (1, 1), (1, 2), (1, 3)]]);
+    assert_eq!(special_for_shape(&line4[0]), Some(TileModifier::StripedVertical));
+    let l = classify_shapes(&[vec![(0, 0), (0, 1), (0, 2)], vec![(0, 0), (1, 0), (2, 0)]]);
+    assert_eq!(special_for_shape(&l[0]), Some(TileModifier::Bomb));
+    let line3 = classify_shapes(&[vec![(0, 0), (0, 1), (0, 2)]]);
+    assert_eq!(special_for_shape(&line3[0]), None);
+  }
+  #[test]
+  fn blast_clears_the_area_of_the_special() {
+    let grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 4]; 4]);
+    assert_eq!(blast(&grid, (1, 2), TileModifier::StripedHorizontal), vec![(0, 2), (1, 2), (2, 2), (3, 2)]);
+    assert_eq!(blast(&grid, (1, 2), TileModifier::StripedVertical), vec![(1, 0), (1, 1), (1, 2), (1, 3)]);
+    assert_eq!(blast(&grid, (0, 0), TileModifier::Bomb), vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+  }
+  #[test]
+  fn expand_cleared_chains_special_tiles() {
+    let grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 4]; 4]);
+    let mut specials = Specials::default();
+    specials.insert((0, 0), TileModifier::StripedVertical);
+    specials.insert((0, 3), TileModifier::StripedHorizontal);
+    let (cleared, triggered) = expand_cleared(&grid, &specials, vec![(0, 0)]);
+    assert_eq!(triggered, vec![
+      ((0, 0), TileModifier::StripedVertical),
+      ((0, 3), TileModifier::StripedHorizontal),
+    ]);
+    assert_eq!(cleared.len(), 7);
+  }
+  #[test]
+  fn activate_swap_clears_the_swapped_colour() {
+    let grid = Grid::from_columns(vec![
+      vec![Some(TileType::Star), Some(TileType::Circle)],
+      vec![Some(TileType::Circle), Some(TileType::Square)],
+    ]);
+    let mut specials = Specials::default();
+    specials.insert((0, 0), TileModifier::ColorBomb);
+    assert!(activates_on_swap(&specials, (0, 1), (0, 0)));
+    let activation = activate_swap(&grid, &mut specials, (0, 0), (0, 1)).unwrap();
+    assert_eq!(activation.cleared, vec![(0, 0), (0, 1), (1, 0)]);
+    assert!(specials.is_empty());
+  }
+  #[test]
+  fn combine_specials_uses_the_combination_table() {
+    use TileModifier::*;
+    assert_eq!(combine_specials(StripedHorizontal, StripedVertical), Combination::Cross);
+    assert_eq!(combine_specials(StripedVertical, StripedVertical), Combination::Cross);
+    assert_eq!(combine_specials(Bomb, StripedHorizontal), Combination::ThickCross);
+    assert_eq!(combine_specials(StripedVertical, Bomb), Combination::ThickCross);
+    assert_eq!(combine_specials(Bomb, Bomb), Combination::BigBomb);
+    assert_eq!(combine_specials(ColorBomb, Bomb), Combination::Spread(Bomb));
+    assert_eq!(combine_specials(StripedHorizontal, ColorBomb), Combination::Spread(StripedHorizontal));
+    assert_eq!(combine_specials(ColorBomb, ColorBomb), Combination::ClearBoard);
+  }
+  #[test]
+  fn apply_combination_clears_lines_around_the_swap() {
+    let grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 5]; 5]);
+    let mut specials = Specials::default();
+    assert_eq!(apply_combination(&grid, &mut specials, Combination::Cross, (2, 2), (2, 3)).len(), 9);
+    assert_eq!(apply_combination(&grid, &mut specials, Combination::ThickCross, (2, 2), (2, 3)).len(), 21);
+    assert_eq!(apply_combination(&grid, &mut specials, Combination::BigBomb, (0, 0), (0, 1)).len(), 9);
+    assert_eq!(apply_combination(&grid, &mut specials, Combination::ClearBoard, (0, 0), (0, 1)).len(), 25);
+  }
+  #[test]
+  fn activate_swap_combines_two_specials() {
+    let grid = Grid::from_columns(vec![vec![Some(TileType::Circle); 5]; 5]);
+    let mut specials = Specials::default();
+    specials.insert((2, 2), TileModifier::StripedHorizontal);
+    specials.insert((2, 3), TileModifier::StripedVertical);
+    assert!(activates_on_swap(&specials, (2, 2), (2, 3)));
+    let activation = activate_swap(&grid, &mut specials, (2, 2), (2, 3)).unwrap();
+    assert_eq!(activation.cleared.len(), 9);
+    assert!(activation.cleared.iter().all(|coord| coord.0 == 2 || coord.1 == 3));
+    assert!(specials.is_empty());
+  }
+  #[test]
+  fn activate_swap_spreads_a_special_with_a_colour_bomb() {
+    let grid = Grid::from_columns(vec![
+      vec![Some(TileType::Star), Some(TileType::Circle), Some(TileType::Square)],
+      vec![Some(TileType::Square), Some(TileType::Star), Some(TileType::Square)],
+      vec![Some(TileType::Star), Some(TileType::Square), Some(TileType::Star)],
+      vec![Some(TileType::Circle), Some(TileType::Star), Some(TileType::Circle)],
+    ]);
+    let mut specials = Specials::default();
+    specials.insert((0, 0), TileModifier::ColorBomb);
+    specials.insert((0, 1), TileModifier::Bomb);
+    specials.insert((3, 2), TileModifier::StripedVertical);
+    let activation = activate_swap(&grid, &mut specials, (0, 1), (0, 0)).unwrap();
+    assert_eq!(activation.triggered, vec![((0, 0), TileModifier::ColorBomb), ((0, 1), TileModifier::Bomb)]);
+    let mut cleared = activation.cleared.clone();
+    cleared.sort();
+    cleared.dedup();
+    // The bomb goes off around itself, and every other circle is cleared
+    assert_eq!(cleared, vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (3, 0), (3, 2)]);
+    assert_eq!(specials.get(&(0, 1)), None);
+    assert_eq!(specials.get(&(3, 0)), Some(&TileModifier::Bomb));
+    assert_eq!(specials.get(&(3, 2)), Some(&TileModifier::StripedVertical));
+    let (_, chained) = expand_cleared(&grid, &specials, activation.cleared);
+    assert_eq!(chained, vec![((3, 0), TileModifier::Bomb), ((3, 2), TileModifier::StripedVertical)]);
+  }
+}
@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+use super::{drag::*, level::*};
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GameState {
+  // Waiting for the player to start a game. There is no board yet.
+  #[default]
+  MainMenu,
+  // Waiting for the player to make a move
+  Playing,
+  // The board is clearing up after a swap, input is ignored until it is done.
+  Resolving,
+  Paused,
+  // The player can't move any more. The board stays up until they go back to the menu.
+  GameOver,
+  // The level has been won or lost, see LevelResult
+  Results,
+}
+
+fn confirm_pressed(keys: &Input<KeyCode>, mouse_buttons: &Input<MouseButton>, touches: &Touches) -> bool {
+  keys.any_just_pressed([KeyCode::Return, KeyCode::Space])
+    || mouse_buttons.just_pressed(MouseButton::Left)
+    || touches.any_just_pressed()
+}
+
+pub fn start_game(
+  keys: Res<Input<KeyCode>>,
+  mouse_buttons: Res<Input<MouseButton>>,
+  touches: Res<Touches>,
+  mut next_state: ResMut<NextState<GameState>>,
+) {
+  if confirm_pressed(&keys, &mouse_buttons, &touches) {
+    next_state.set(GameState::Playing);
+  }
+}
+
+// Escape in the middle of a drag only cancels the drag.
+pub fn toggle_pause(
+  keys: Res<Input<KeyCode>>,
+  state: Res<State<GameState>>,
+  mut next_state: ResMut<NextState<GameState>>,
+  dragging: Query<(), With<TileIsDragging>>,
+) {
+  if !keys.just_pressed(KeyCode::Escape) || !dragging.is_empty() {
+    return;
+  }
+  match state.0 {
+    GameState::Playing => next_state.set(GameState::Paused),
+    GameState::Paused => next_state.set(GameState::Playing),
+    _ => {}
+  }
+}
+
+pub fn leave_game_over(
+  keys: Res<Input<KeyCode>>,
+  mouse_buttons: Res<Input<MouseButton>>,
+  touches: Res<Touches>,
+  mut next_state: ResMut<NextState<GameState>>,
+) {
+  if confirm_pressed(&keys, &mouse_buttons, &touches) {
+    next_state.set(GameState::MainMenu);
+  }
+}
+
+pub fn add_state_to_app(app: &mut App) -> &mut App {
+  app
+    .add_state::<GameState>()
+    .add_system(start_game.run_if(in_state(GameState::MainMenu).and_then(level_loaded)))
+    .add_system(toggle_pause.run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))))
+    .add_system(leave_game_over.run_if(in_state(GameState::GameOver).or_else(in_state(GameState::Results))))
+}
@@ -7,4 +7,13 @@ pub struct MouseInput;
 pub struct GameEvents;
 
 #[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
-pub struct Cleanup;
\ No newline at end of file
+pub struct Cleanup;
+
+#[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
+pub struct Resolve;
+
+#[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
+pub struct Shuffle;
+
+#[derive(SystemSet, Clone, Hash, Debug, Eq, PartialEq)]
+pub struct Animation;
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use rand::{Rng, seq::SliceRandom};
+use serde::Deserialize;
+
+use super::grid::*;
+
+#[derive(Debug, Clone, PartialEq, Reflect, FromReflect, Deserialize)]
+pub struct TileKind {
+  pub tile_type: TileType,
+  // Path of the image in the assets folder
+  pub texture: String,
+  // Multiplied with the image, so one image can be used for more than one kind
+  #[serde(default)]
+  pub tint: Color,
+  // How often this kind is picked compared to the others
+  #[serde(default = "default_weight")]
+  pub weight: f32,
+}
+
+fn default_weight() -> f32 {
+  1.0
+}
+
+impl TileKind {
+  pub fn new(tile_type: TileType, texture: &str) -> Self {
+    TileKind {
+      tile_type,
+      texture: texture.to_string(),
+      tint: Color::WHITE,
+      weight: 1.0,
+    }
+  }
+
+  #[cfg(test)]
+  pub fn with_weight(mut self, weight: f32) -> Self {
+    self.weight = weight;
+    self
+  }
+}
+
+pub const INGREDIENT_TINT: Color = Color::rgb(0.75, 0.5, 0.25);
+
+impl TileKind {
+  // Ingredients are placed by levels and never picked at random, so they are not part of any tile set.
+  pub fn ingredient() -> Self {
+    TileKind {
+      tint: INGREDIENT_TINT,
+      ..TileKind::new(TileType::Ingredient, "square.png")
+    }
+  }
+}
+
+// The kinds of tile that can appear on the board, and so the number of colours in play.
+// New tiles, when the board is created and when it is refilled, are picked from these by weight.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect, Deserialize)]
+pub struct TileSet {
+  pub kinds: Vec<TileKind>,
+}
+
+impl Default for TileSet {
+  fn default() -> Self {
+    TileSet::with_colors(6)
+  }
+}
+
+impl TileSet {
+  // With fewer colours nearly every refill makes a shape, so a cascade could go on forever.
+  pub const MIN_COLORS: usize = 3;
+
+  // Every kind there is, in the order colours are added by with_colors.
+  pub fn all() -> Self {
+    TileSet {
+      kinds: vec![
+        TileKind::new(TileType::Pentagon, "pentagon.png"),
+        TileKind::new(TileType::Triangle, "triangle.png"),
+        TileKind::new(TileType::Square, "square.png"),
+        TileKind::new(TileType::Circle, "circle.png"),
+        TileKind::new(TileType::Diamond, "diamond.png"),
+        TileKind::new(TileType::Star, "star.png"),
+        // The square is grey, so it is tinted to make a colour of its own
+        TileKind {
+          tint: Color::rgb(0.45, 1.0, 0.55),
+          ..TileKind::new(TileType::Emerald, "square.png")
+        },
+      ],
+    }
+  }
+
+  // The first few kinds with equal weights. Fewer colours make matches easier to find.
+  pub fn with_colors(colors: usize) -> Self {
+    let mut tile_set = TileSet::all();
+    tile_set.kinds.truncate(colors);
+    tile_set
+  }
+
+  #[cfg(test)]
+  pub fn get(&self, tile_type: TileType) -> Option<&TileKind> {
+    self.kinds.iter().find(|kind| kind.tile_type == tile_type)
+  }
+
+  pub fn random(&self, rng: &mut impl Rng) -> TileType {
+    self
+      .kinds
+      .choose_weighted(rng, |kind| kind.weight)
+      .expect("the tile set needs at least one kind with a weight above zero")
+      .tile_type
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{SeedableRng, rngs::StdRng};
+  #[test]
+  fn random_only_picks_kinds_in_the_set() {
+    let tile_set = TileSet::with_colors(4);
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..200 {
+      assert!(tile_set.get(tile_set.random(&mut rng)).is_some());
+    }
+  }
+  #[test]
+  fn random_follows_the_weights() {
+    let tile_set = TileSet {
+      kinds: vec![
+        TileKind::new(TileType::Circle, "circle.png").with_weight(3.0),
+        TileKind::new(TileType::Star, "star.png"),
+        TileKind::new(TileType::Square, "square.png").with_weight(0.0),
+      ],
+    };
+    let mut rng = StdRng::seed_from_u64(2);
+    let picks = (0..1000).map(|_| tile_set.random(&mut rng)).collect::<Vec<_>>();
+    let circles = picks.iter().filter(|&&tile_type| tile_type == TileType::Circle).count();
+    assert!((650..850).contains(&circles), "{}", circles);
+    assert!(!picks.contains(&TileType::Square));
+  }
+}
@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+
+use super::{state::*, system_sets::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect, FromReflect)]
+pub enum Easing {
+  #[default]
+  Linear,
+  // Starts and ends slowly
+  SmoothStep,
+  // Speeds up towards the end, like a falling tile
+  EaseIn,
+  // Slows down towards the end
+  EaseOut,
+  // Overshoots the end a little before settling back onto it
+  BackOut,
+}
+
+impl Easing {
+  // Map the fraction of the tween that has elapsed to how far along the path the entity should be.
+  pub fn apply(&self, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match self {
+      Easing::Linear => t,
+      Easing::SmoothStep => t * t * (3.0 - 2.0 * t),
+      Easing::EaseIn => t * t,
+      Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+      Easing::BackOut => {
+        let overshoot = 1.70158;
+        let u = t - 1.0;
+        1.0 + (overshoot + 1.0) * u * u * u + overshoot * u * u
+      }
+    }
+  }
+}
+
+// Moves an entity's translation from start to end.
+// The component is removed once the tween is done, so a query for Tween tells whether anything is still moving.
+#[derive(Component, Debug, Clone, Reflect)]
+pub struct Tween {
+  pub start: Vec3,
+  pub end: Vec3,
+  pub easing: Easing,
+  pub timer: Timer,
+}
+
+impl Tween {
+  pub fn new(start: Vec3, end: Vec3, seconds: f32, easing: Easing) -> Self {
+    Tween {
+      start,
+      end,
+      easing,
+      timer: Timer::from_seconds(seconds, TimerMode::Once),
+    }
+  }
+
+  pub fn translation(&self) -> Vec3 {
+    self.start.lerp(self.end, self.easing.apply(self.timer.percent()))
+  }
+}
+
+pub fn animate_tweens(
+  mut commands: Commands,
+  time: Res<Time>,
+  mut tweens: Query<(Entity, &mut Tween, &mut Transform)>,
+) {
+  for (entity, mut tween, mut transform) in tweens.iter_mut() {
+    tween.timer.tick(time.delta());
+    transform.translation = tween.translation();
+    if tween.timer.finished() {
+      commands.entity(entity).remove::<Tween>();
+    }
+  }
+}
+
+// Run condition for systems that have to wait for everything on the board to stop moving.
+pub fn tweens_finished(tweens: Query<(), With<Tween>>) -> bool {
+  tweens.is_empty()
+}
+
+pub fn add_tween_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<Easing>()
+    .register_type::<Tween>()
+    .configure_set(Animation.after(Shuffle).run_if(not(in_state(GameState::Paused))))
+    .add_system(animate_tweens.in_set(Animation))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn easings_start_at_zero_and_end_at_one() {
+    for easing in [Easing::Linear, Easing::SmoothStep, Easing::EaseIn, Easing::EaseOut, Easing::BackOut] {
+      assert!(easing.apply(0.0).abs() < 1e-5, "{:?}", easing);
+      assert!((easing.apply(1.0) - 1.0).abs() < 1e-5, "{:?}", easing);
+    }
+    assert!(Easing::EaseIn.apply(0.5) < 0.5);
+    assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    assert!((0..10).any(|step| Easing::BackOut.apply(step as f32 / 10.0) > 1.0));
+  }
+  #[test]
+  fn tween_moves_from_start_to_end() {
+    let mut tween = Tween::new(Vec3::ZERO, Vec3::new(80.0, 0.0, 0.0), 1.0, Easing::Linear);
+    assert_eq!(tween.translation(), Vec3::ZERO);
+    tween.timer.tick(std::time::Duration::from_secs_f32(0.25));
+    assert_eq!(tween.translation(), Vec3::new(20.0, 0.0, 0.0));
+    tween.timer.tick(std::time::Duration::from_secs(1));
+    assert_eq!(tween.translation(), Vec3::new(80.0, 0.0, 0.0));
+  }
+}
@@ -0,0 +1,187 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use super::{components::*, level::*, resources::*, score::*, state::*, system_sets::*};
+
+pub const FONT: &str = "fonts/DejaVuSans.ttf";
+
+// Text in the middle of the screen, removed with the overlay.
+fn spawn_message(commands: &mut Commands, asset_server: &AssetServer, message: String) {
+  commands
+    .spawn((
+      NodeBundle {
+        style: Style {
+          size: Size::all(Val::Percent(100.0)),
+          justify_content: JustifyContent::Center,
+          align_items: AlignItems::Center,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      Overlay,
+      Name::new("Message"),
+    ))
+    .with_children(|parent| {
+      parent.spawn(
+        TextBundle::from_section(message, TextStyle {
+          font: asset_server.load(FONT),
+          font_size: 32.0,
+          color: Color::WHITE,
+        })
+        .with_text_alignment(TextAlignment::Center),
+      );
+    });
+}
+
+// Waits for the level to load, so that it can be named and the game can be started right away.
+pub fn show_main_menu(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+  selected_level: Option<Res<SelectedLevel>>,
+  failed_level: Option<Res<FailedLevel>>,
+  levels: Res<Assets<Level>>,
+  overlays: Query<(), With<Overlay>>,
+) {
+  if !overlays.is_empty() {
+    return;
+  }
+  let prompt = "Press Enter, click or tap to start";
+  let message = match (selected_level.and_then(|selected_level| levels.get(&selected_level.0)), failed_level) {
+    (Some(level), _) => format!("{}\n{}", level.name, prompt),
+    (None, Some(failed_level)) => format!("Could not load {}\n{}", failed_level.0, prompt),
+    (None, None) => prompt.to_string(),
+  };
+  spawn_message(&mut commands, &asset_server, message);
+}
+
+pub fn show_pause(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+) {
+  spawn_message(&mut commands, &asset_server, "Paused\nPress Escape to continue".to_string());
+}
+
+pub fn show_game_over(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+  score: Res<Score>,
+) {
+  spawn_message(
+    &mut commands,
+    &asset_server,
+    format!("Game over\n{} points\nPress Enter, click or tap to go back to the menu", score.value),
+  );
+}
+
+pub fn show_results(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+  score: Res<Score>,
+  level_result: Res<LevelResult>,
+) {
+  let result = if level_result.won {
+    format!("Level complete\n{} points, {} stars", score.value, level_result.stars)
+  } else {
+    format!("Level failed\n{} points", score.value)
+  };
+  spawn_message(
+    &mut commands,
+    &asset_server,
+    format!("{}\nPress Enter, click or tap to go back to the menu", result),
+  );
+}
+
+// Darken the board while the game is paused or over.
+pub fn spawn_overlay(
+  mut commands: Commands,
+  game_config: Res<GameConfig>,
+) {
+  commands.spawn((
+    SpriteBundle {
+      transform: Transform::from_xyz(0.0, 0.0, 10.0),
+      sprite: Sprite {
+        color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+        custom_size: Some(Vec2::new(
+          game_config.cell_size * game_config.columns as f32,
+          game_config.cell_size * game_config.rows as f32,
+        )),
+        anchor: Anchor::BottomLeft,
+        ..Default::default()
+      },
+      ..Default::default()
+    },
+    Overlay,
+    Name::new("Overlay"),
+  ));
+}
+
+pub fn despawn_overlay(
+  mut commands: Commands,
+  overlays: Query<Entity, With<Overlay>>,
+) {
+  for entity in overlays.iter() {
+    commands.entity(entity).despawn_recursive();
+  }
+}
+
+pub fn spawn_score_text(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+) {
+  commands.spawn((
+    TextBundle::from_section("Score 0", TextStyle {
+      font: asset_server.load(FONT),
+      font_size: 24.0,
+      color: Color::WHITE,
+    })
+    .with_style(Style {
+      position_type: PositionType::Absolute,
+      position: UiRect {
+        left: Val::Px(10.0),
+        top: Val::Px(10.0),
+        ..Default::default()
+      },
+      ..Default::default()
+    }),
+    ScoreText,
+    Name::new("Score"),
+  ));
+}
+
+// Shows the points the last clear was worth next to the score.
+pub fn show_score(
+  mut score_changed_evr: EventReader<ScoreChanged>,
+  mut score_text: Query<&mut Text, With<ScoreText>>,
+) {
+  let Some(evt) = score_changed_evr.iter().last() else {
+    return;
+  };
+  for mut text in score_text.iter_mut() {
+    text.sections[0].value = format!("Score {} (+{})", evt.score, evt.breakdown.total);
+  }
+}
+
+pub fn despawn_score_text(
+  mut commands: Commands,
+  score_text: Query<Entity, With<ScoreText>>,
+) {
+  for entity in score_text.iter() {
+    commands.entity(entity).despawn_recursive();
+  }
+}
+
+pub fn add_ui_to_app(app: &mut App) -> &mut App {
+  app
+    .register_type::<Overlay>()
+    .register_type::<ScoreText>()
+    .add_system(show_main_menu.run_if(in_state(GameState::MainMenu).and_then(level_loaded)))
+    .add_system(despawn_overlay.in_schedule(OnExit(GameState::MainMenu)))
+    .add_systems((show_pause, spawn_overlay).in_schedule(OnEnter(GameState::Paused)))
+    .add_system(despawn_overlay.in_schedule(OnExit(GameState::Paused)))
+    .add_systems((show_game_over, spawn_overlay).in_schedule(OnEnter(GameState::GameOver)))
+    .add_system(despawn_overlay.in_schedule(OnExit(GameState::GameOver)))
+    .add_systems((show_results, spawn_overlay).in_schedule(OnEnter(GameState::Results)))
+    .add_system(despawn_overlay.in_schedule(OnExit(GameState::Results)))
+    .add_system(spawn_score_text.in_schedule(OnExit(GameState::MainMenu)))
+    .add_system(despawn_score_text.in_schedule(OnEnter(GameState::MainMenu)))
+    .add_system(show_score.after(Resolve))
+}